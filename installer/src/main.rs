@@ -19,28 +19,6 @@ pub fn install_sccache() {
     }
 }
 
-pub fn set_compiler_nightly() {
-    println!("{}", "> rustup default nightly".bright_cyan());
-
-    // Set nightly compiler
-    let status = Command::new("rustup")
-        .arg("default")
-        .arg("nightly")
-        .status()
-        .unwrap();
-
-    if status.success() {
-        println!(
-            "{} {} {}",
-            "Switched to".bright_green(),
-            "nightly".bright_blue(),
-            "compiler".bright_green(),
-        );
-    } else {
-        println!("{}", "Failed to set nightly compiler".bright_red());
-    }
-}
-
 pub fn install_fleet() {
     println!("{}", "> cargo install fleet-rs".bright_cyan());
 
@@ -77,7 +55,6 @@ fn main() {
                 )
             );
 
-            set_compiler_nightly();
             install_sccache();
             install_fleet();
 
@@ -99,7 +76,6 @@ fn main() {
                 )
             );
 
-            set_compiler_nightly();
             install_sccache();
             install_fleet();
 
@@ -110,7 +86,6 @@ fn main() {
 
             let _ = enable_ansi_support::enable_ansi_support();
 
-            set_compiler_nightly();
             install_sccache();
             install_fleet();
 
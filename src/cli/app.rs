@@ -15,51 +15,102 @@
  *    limitations under the License.
  */
 
-use crate::core::config::FleetConfig;
+use crate::core::config::{find_root, layered::LayeredConfig, FleetConfig};
 use clap::{
-    arg, crate_authors, crate_description, crate_name, crate_version, Command as CliCommand, Values,
+    arg, crate_authors, crate_description, crate_name, crate_version, ArgMatches,
+    Command as CliCommand, Values,
 };
 use colored::Colorize;
 use std::{env::current_dir, path::PathBuf};
 
 use crate::cli::help;
-use crate::core::commands::{bloat, build, configure, init, run, udeps};
-use anyhow::Result;
+use crate::core::commands::{
+    bloat, build, config, configure, doctor, eject, init, ramdisk, run, udeps,
+};
+use anyhow::{Context, Result};
 use std::process::{self, exit};
 
 pub enum Command {
-    Init(Option<Values<'static>>),
-    Build(Option<Values<'static>>),
-    Run(Option<Values<'static>>),
+    Init(init::InitOptions),
+    Build {
+        args: Option<Values<'static>>,
+        plain: bool,
+    },
+    Run {
+        args: Option<Values<'static>>,
+        plain: bool,
+    },
     Bloat(Option<Values<'static>>),
-    Configure(Option<Values<'static>>),
+    Configure {
+        dry_run: bool,
+    },
+    Config {
+        action: config::ConfigAction,
+    },
+    Ramdisk {
+        action: ramdisk::RamdiskAction,
+    },
     Udeps(Option<Values<'static>>),
+    Eject(Option<Values<'static>>),
+    Doctor {
+        json: bool,
+    },
 }
 
 pub struct App {
-    pub config: FleetConfig,
+    /// The `fleet.toml` of the project, `None` until it is loaded or when the project is not initialized
+    pub config: Option<FleetConfig>,
     pub current_dir: PathBuf,
-}
-
-impl Default for App {
-    fn default() -> Self {
-        Self::new()
-    }
+    /// Root of the project, where `fleet.toml` and the cargo config live
+    pub root_dir: PathBuf,
+    /// Values passed with `--config key=value`
+    pub overrides: Vec<String>,
 }
 
 impl App {
     ///Creates a new app
     ///
-    /// # Panics
-    /// Can panic if current directory is not found (eg. doesn't exist or invalid perms)
-    #[must_use]
-    pub fn new() -> Self {
-        let current_dir = current_dir().expect("Unable to find current directory for app!");
-
-        Self {
-            config: FleetConfig::run_config(),
+    /// The project root is found by walking up from the current directory,
+    /// falling back to the current directory outside of a cargo project.
+    ///
+    /// The config is loaded with [`App::load_config`] by the commands that need it, so that a
+    /// broken `fleet.toml` can still be repaired or replaced.
+    pub fn new() -> Result<Self> {
+        let current_dir = current_dir().context("unable to find current directory for app")?;
+        let root_dir = find_root(&current_dir).unwrap_or_else(|| current_dir.clone());
+
+        Ok(Self {
+            config: None,
             current_dir,
+            root_dir,
+            overrides: Vec::new(),
+        })
+    }
+
+    /// Reads every config layer of the project
+    pub fn layers(&self) -> Result<LayeredConfig> {
+        LayeredConfig::load(&self.root_dir, &self.current_dir, &self.overrides)
+    }
+
+    /// Resolves the config of the project from its layers
+    pub fn load_config(&mut self) -> Result<()> {
+        self.config = self.layers()?.config()?;
+
+        Ok(())
+    }
+
+    /// Returns the config of the project, creating `fleet.toml` if the project is not initialized
+    pub fn config_or_create(&mut self) -> Result<FleetConfig> {
+        self.load_config()?;
+
+        if self.config.is_none() {
+            FleetConfig::create(&self.root_dir)?;
+            self.load_config()?;
         }
+
+        self.config
+            .clone()
+            .context("fleet.toml could not be loaded")
     }
 
     fn build<'a>(&self) -> CliCommand<'a> {
@@ -72,32 +123,66 @@ impl App {
                     .required(false)
                     .default_missing_value(""),
             )
+            .arg(
+                arg!(--config <OVERRIDE> "Override a config value, eg. `--config build.linker=mold`")
+                    .required(false)
+                    .multiple_occurrences(true),
+            )
             .subcommand(
                 CliCommand::new("init")
                     .about("Initialize a fleet project")
-                    .arg(arg!([EXTRA]).multiple_values(true)),
+                    .arg(arg!(--bin "Use a binary (application) template [default]"))
+                    .arg(arg!(--lib "Use a library template").conflicts_with("bin"))
+                    .arg(arg!(--name <NAME> "Set the resulting package name").required(false))
+                    .arg(arg!(--force "Overwrite an existing fleet.toml"))
+                    .arg(arg!(--configure "Select a linker once the project is initialized")),
             )
             .subcommand(
                 CliCommand::new("run")
                     .about("Runs the fleet project")
+                    .allow_hyphen_values(true)
+                    .arg(arg!(--plain "Print the output of cargo instead of the progress display"))
                     .arg(arg!([EXTRA]).multiple_values(true)),
             )
             .subcommand(
                 CliCommand::new("build")
                     .about("Builds a fleet project")
+                    .allow_hyphen_values(true)
+                    .arg(arg!(--plain "Print the output of cargo instead of the progress display"))
                     .arg(arg!([EXTRA]).multiple_values(true)),
             )
-            .subcommand(CliCommand::new("configure").about("Configure a fleet project"))
+            .subcommand(
+                CliCommand::new("configure")
+                    .about("Configure a fleet project")
+                    .arg(arg!(--"dry-run" "Only print the commands used to install the linker")),
+            )
+            .subcommand(config_command())
+            .subcommand(ramdisk_command())
             .subcommand(CliCommand::new("bloat").about("?"))
             .subcommand(CliCommand::new("udeps").about("?"))
+            .subcommand(
+                CliCommand::new("doctor")
+                    .about("Diagnose the fleet setup of the project")
+                    .arg(arg!(--json "Print the report as JSON")),
+            )
+            .subcommand(
+                CliCommand::new("eject").about(
+                    "Remove the configuration generated by fleet and go back to plain cargo",
+                ),
+            )
     }
 
-    fn get_command(&self) -> Command {
+    fn get_command(&mut self) -> Command {
         let mut options = self.build();
 
         // There should be a better way to implement this
         let matches = Box::leak(options.clone().get_matches().into());
 
+        self.overrides = matches
+            .values_of("config")
+            .map(|values| values.map(ToString::to_string).collect())
+            .unwrap_or_default();
+
         match matches.value_of("command") {
             Some(cmd) => {
                 match cmd {
@@ -116,12 +201,41 @@ impl App {
         }
 
         match matches.subcommand() {
-            Some(("init", _sub)) => Command::Init(None),
-            Some(("build", sub)) => Command::Build(sub.values_of("EXTRA")),
-            Some(("run", sub)) => Command::Run(sub.values_of("EXTRA")),
+            Some(("init", sub)) => Command::Init(init::InitOptions {
+                kind: if sub.is_present("lib") {
+                    Some(init::PackageKind::Lib)
+                } else if sub.is_present("bin") {
+                    Some(init::PackageKind::Bin)
+                } else {
+                    None
+                },
+                name: sub.value_of("name").map(ToString::to_string),
+                force: sub.is_present("force"),
+                configure: sub.is_present("configure"),
+            }),
+            Some(("build", sub)) => Command::Build {
+                args: sub.values_of("EXTRA"),
+                plain: sub.is_present("plain"),
+            },
+            Some(("run", sub)) => Command::Run {
+                args: sub.values_of("EXTRA"),
+                plain: sub.is_present("plain"),
+            },
             Some(("bloat", _sub)) => Command::Bloat(None),
             Some(("udeps", _sub)) => Command::Udeps(None),
-            Some(("configure", _sub)) => Command::Configure(None),
+            Some(("configure", sub)) => Command::Configure {
+                dry_run: sub.is_present("dry-run"),
+            },
+            Some(("config", sub)) => Command::Config {
+                action: config_action(sub),
+            },
+            Some(("ramdisk", sub)) => Command::Ramdisk {
+                action: ramdisk_action(sub),
+            },
+            Some(("eject", _sub)) => Command::Eject(None),
+            Some(("doctor", sub)) => Command::Doctor {
+                json: sub.is_present("json"),
+            },
             _ => {
                 options.print_help().unwrap_or_else(|_| {
                     eprintln!("{}", "Failed to display help.".red(),);
@@ -132,16 +246,124 @@ impl App {
         }
     }
 
-    pub fn run(self) -> Result<()> {
+    pub fn run(mut self) -> Result<()> {
         let command = self.get_command();
 
         match command {
-            Command::Init(args) => init::run(self, args),
-            Command::Build(args) => build::run(self, args),
-            Command::Run(args) => run::run(self, args),
+            Command::Init(options) => init::run(self, &options),
+            Command::Build { args, plain } => build::run(self, args, plain),
+            Command::Run { args, plain } => run::run(self, args, plain),
             Command::Bloat(args) => bloat::run(self, args),
-            Command::Configure(args) => configure::run(self, args),
+            Command::Configure { dry_run } => configure::run(self, dry_run),
+            Command::Config { action } => config::run(self, action),
+            Command::Ramdisk { action } => ramdisk::run(self, action),
             Command::Udeps(args) => udeps::run(self, args),
+            Command::Eject(args) => eject::run(self, args),
+            Command::Doctor { json } => doctor::run(self, json),
         }
     }
 }
+
+/// Subcommands of `fleet config`
+fn config_command<'a>() -> CliCommand<'a> {
+    CliCommand::new("config")
+        .about("Read or write the fleet config")
+        .subcommand_required(true)
+        .subcommand(
+            CliCommand::new("get")
+                .about("Print the effective value of a key")
+                .arg(arg!(<KEY> "Config key, eg. `build.sccache`"))
+                .arg(arg!(--"show-origin" "Show where the value comes from")),
+        )
+        .subcommand(
+            CliCommand::new("set")
+                .about("Set a key in fleet.toml")
+                .arg(arg!(<KEY> "Config key, eg. `build.sccache`"))
+                .arg(arg!(<VALUE> "New value of the key"))
+                .arg(arg!(--global "Write to the global config file")),
+        )
+        .subcommand(
+            CliCommand::new("unset")
+                .about("Remove a key from fleet.toml")
+                .arg(arg!(<KEY> "Config key, eg. `build.sccache`"))
+                .arg(arg!(--global "Write to the global config file")),
+        )
+        .subcommand(
+            CliCommand::new("edit")
+                .about("Open fleet.toml in $EDITOR")
+                .arg(arg!(--global "Edit the global config file")),
+        )
+        .subcommand(
+            CliCommand::new("list")
+                .about("Print every effective value")
+                .arg(arg!(--"show-origin" "Show where each value comes from")),
+        )
+        .subcommand(
+            CliCommand::new("schema")
+                .about("Print the JSON Schema of fleet.toml")
+                .arg(arg!(--global "Print the schema of the global config file")),
+        )
+}
+
+/// Subcommands of `fleet ramdisk`
+fn ramdisk_command<'a>() -> CliCommand<'a> {
+    CliCommand::new("ramdisk")
+        .about("Manage the ramdisk of the project")
+        .subcommand_required(true)
+        .subcommand(CliCommand::new("status").about("Show whether the project builds on a ramdisk"))
+        .subcommand(CliCommand::new("enable").about("Build the project on a ramdisk"))
+        .subcommand(
+            CliCommand::new("disable").about("Move the artifacts back to target and build on disk"),
+        )
+        .subcommand(
+            CliCommand::new("clean")
+                .about("Remove the ramdisk of the project and its snapshot")
+                .arg(arg!(--all "Remove the ramdisk of every project")),
+        )
+        .subcommand(CliCommand::new("list").about("List the ramdisk of every project"))
+        .subcommand(CliCommand::new("sync").about("Save a snapshot of the ramdisk to disk"))
+}
+
+/// Reads the action of `fleet config` from its matches
+fn config_action(matches: &ArgMatches) -> config::ConfigAction {
+    match matches.subcommand() {
+        Some(("get", sub)) => config::ConfigAction::Get {
+            key: sub.value_of("KEY").unwrap_or_default().to_string(),
+            show_origin: sub.is_present("show-origin"),
+        },
+        Some(("set", sub)) => config::ConfigAction::Set {
+            key: sub.value_of("KEY").unwrap_or_default().to_string(),
+            value: sub.value_of("VALUE").unwrap_or_default().to_string(),
+            global: sub.is_present("global"),
+        },
+        Some(("unset", sub)) => config::ConfigAction::Unset {
+            key: sub.value_of("KEY").unwrap_or_default().to_string(),
+            global: sub.is_present("global"),
+        },
+        Some(("edit", sub)) => config::ConfigAction::Edit {
+            global: sub.is_present("global"),
+        },
+        Some(("list", sub)) => config::ConfigAction::List {
+            show_origin: sub.is_present("show-origin"),
+        },
+        Some(("schema", sub)) => config::ConfigAction::Schema {
+            global: sub.is_present("global"),
+        },
+        _ => unreachable!("a subcommand of `fleet config` is required"),
+    }
+}
+
+/// Reads the action of `fleet ramdisk` from its matches
+fn ramdisk_action(matches: &ArgMatches) -> ramdisk::RamdiskAction {
+    match matches.subcommand() {
+        Some(("status", _sub)) => ramdisk::RamdiskAction::Status,
+        Some(("enable", _sub)) => ramdisk::RamdiskAction::Enable,
+        Some(("disable", _sub)) => ramdisk::RamdiskAction::Disable,
+        Some(("clean", sub)) => ramdisk::RamdiskAction::Clean {
+            all: sub.is_present("all"),
+        },
+        Some(("list", _sub)) => ramdisk::RamdiskAction::List,
+        Some(("sync", _sub)) => ramdisk::RamdiskAction::Sync,
+        _ => unreachable!("a subcommand of `fleet ramdisk` is required"),
+    }
+}
@@ -27,6 +27,7 @@ The blazing fast build tool for Rust.
     fleet build [OPTIONS]
 
 {}:
+        --plain                     Print the output of cargo instead of the progress display
     -q, --quiet                     Do not print cargo log messages
     -p, --package [<SPEC>]          Package to build (see `cargo help pkgid`)
         --workspace                 Build all packages in the workspace
@@ -86,6 +87,7 @@ The blazing fast build tool for Rust.
 fleet run [OPTIONS]
 
 {}:
+    --plain                     Print the output of cargo instead of the progress display
 -q, --quiet                     Do not print cargo log messages
     --bin [<NAME>]              Name of the bin target to run
     --example [<NAME>]          Name of the example target to run
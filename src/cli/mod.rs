@@ -17,4 +17,5 @@
 
 pub mod app;
 pub mod help;
+pub mod progress;
 pub mod prompt;
\ No newline at end of file
@@ -0,0 +1,386 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use crate::core::invocation::CargoEvent;
+use colored::Colorize;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::fmt::Write;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Arguments whose output would be hidden or garbled by the progress display
+const PLAIN_ARGS: &[&str] = &[
+    "-q",
+    "--quiet",
+    "-v",
+    "-vv",
+    "--verbose",
+    "--message-format",
+    "--unit-graph",
+    "--build-plan",
+];
+
+/// Crates shown on the second line of the display, the rest are counted
+const SHOWN_UNITS: usize = 4;
+
+/// Checks whether cargo output should be printed as is instead of the progress display
+///
+/// The display needs a terminal, and is never used on CI or with arguments that change what cargo
+/// prints, eg. `--verbose`.
+#[must_use]
+pub fn is_plain(plain: bool, args: &[&str]) -> bool {
+    plain
+        || !std::io::stderr().is_terminal()
+        || std::env::var_os("CI").is_some()
+        || args.iter().any(|arg| {
+            PLAIN_ARGS
+                .iter()
+                .any(|plain| arg == plain || arg.starts_with(&format!("{plain}=")))
+        })
+}
+
+/// Removes the color codes cargo adds with `--color=always`
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+
+    while let Some(char) = chars.next() {
+        if char == '\x1b' {
+            // Skips the sequence up to its final letter, eg. `\x1b[1;32m`
+            for char in chars.by_ref() {
+                if char.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(char);
+        }
+    }
+
+    stripped
+}
+
+/// Formats a count followed by `word`, eg. `1 warning` or `2 warnings`
+fn plural(count: u64, word: &str) -> String {
+    if count == 1 {
+        format!("{count} {word}")
+    } else {
+        format!("{count} {word}s")
+    }
+}
+
+/// Cache hits and misses reported by `sccache --show-stats`
+#[derive(Debug, Clone, Copy, Default)]
+struct SccacheStats {
+    hits: u64,
+    misses: u64,
+}
+
+impl SccacheStats {
+    /// Reads the stats of the sccache server, `None` if it is not running or too old
+    fn read(sccache: &Path) -> Option<Self> {
+        let output = Command::new(sccache)
+            .args(["--show-stats", "--stats-format=json"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+
+        let stats: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+
+        let total = |name: &str| -> u64 {
+            stats["stats"][name]["counts"]
+                .as_object()
+                .map_or(0, |counts| {
+                    counts.values().filter_map(serde_json::Value::as_u64).sum()
+                })
+        };
+
+        Some(Self {
+            hits: total("cache_hits"),
+            misses: total("cache_misses"),
+        })
+    }
+}
+
+/// Message of the spinner of `fleet bloat` and `fleet udeps`, fed with [`CargoEvent`]s
+#[derive(Debug, Default)]
+pub struct SpinnerStatus {
+    warnings: u64,
+    errors: u64,
+}
+
+impl SpinnerStatus {
+    /// Formats a crate with its version, eg. `Compile (serde@1.0.0)`
+    fn crate_message(verb: &str, name: &str, version: &str) -> String {
+        format!(
+            "{} ({}{}{})",
+            verb.bright_cyan(),
+            name.bright_yellow(),
+            "@".bright_magenta(),
+            version.trim_start_matches('v').bright_black(),
+        )
+    }
+
+    /// Returns the new message of the spinner, `None` when `event` does not change it
+    ///
+    /// Diagnostics are counted by level, downloads are read from the status lines of cargo.
+    pub fn message(&mut self, event: &CargoEvent) -> Option<String> {
+        match event {
+            CargoEvent::Artifact(artifact) if !artifact.fresh => Some(Self::crate_message(
+                "Compile",
+                artifact.package_id.name(),
+                artifact.package_id.version(),
+            )),
+            CargoEvent::Message(message) => {
+                match message.message.level.as_str() {
+                    "warning" => self.warnings += 1,
+                    "error" => self.errors += 1,
+                    _ => return None,
+                }
+
+                Some(format!(
+                    "{} ({} {}, {} {})",
+                    "Check".bright_cyan(),
+                    self.warnings.to_string().bright_magenta(),
+                    "warnings".bright_yellow(),
+                    self.errors.to_string().bright_red(),
+                    "errors".bright_yellow(),
+                ))
+            }
+            CargoEvent::Stderr(line) => {
+                let line = strip_ansi(line);
+                let mut words = line.split_whitespace();
+
+                match (words.next(), words.next(), words.next()) {
+                    (Some("Downloaded"), Some(name), Some(version)) => {
+                        Some(Self::crate_message("Download", name, version))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Multi-line display of a cargo build, fed with [`CargoEvent`]s
+///
+/// The first line shows the completed units out of the total, when known, and the elapsed
+/// time, the second one the crates being compiled. Diagnostics are printed above the display.
+///
+/// Progress comes from the JSON messages of cargo, which only report units once they are built.
+/// The crates being compiled are read from the human status lines of cargo, so the second line
+/// is best effort: it falls back to the last built crate and may be missing.
+pub struct BuildProgress {
+    bar: ProgressBar,
+    /// Crates cargo started compiling, removed once their artifact is reported
+    compiling: Vec<String>,
+    /// The last crate reported by an artifact or build script message
+    last_built: Option<String>,
+    completed: u64,
+    fresh: u64,
+    warnings: u64,
+    errors: u64,
+    sccache: Option<(PathBuf, SccacheStats)>,
+}
+
+impl BuildProgress {
+    /// Starts the display, `total` is the number of units cargo is expected to build
+    ///
+    /// The sccache hit rate of the build is reported when `sccache` is the rustc wrapper.
+    #[must_use]
+    pub fn new(total: Option<u64>, sccache: Option<&Path>) -> Self {
+        let bar = ProgressBar::with_draw_target(total.unwrap_or(0), ProgressDrawTarget::stderr());
+
+        let template = if total.is_some() {
+            "{spinner:.green} {prefix:.cyan.bold} [{bar:30.cyan/blue}] {pos}/{len} {elapsed_precise:.dim}\n  {wide_msg}"
+        } else {
+            "{spinner:.green} {prefix:.cyan.bold} {pos} {elapsed_precise:.dim}\n  {wide_msg}"
+        };
+
+        bar.set_style(
+            ProgressStyle::default_bar()
+                .template(template)
+                .progress_chars("=> "),
+        );
+        bar.set_prefix("Building");
+        bar.enable_steady_tick(100);
+
+        Self {
+            bar,
+            compiling: Vec::new(),
+            last_built: None,
+            completed: 0,
+            fresh: 0,
+            warnings: 0,
+            errors: 0,
+            sccache: sccache
+                .and_then(|sccache| Some((sccache.to_path_buf(), SccacheStats::read(sccache)?))),
+        }
+    }
+
+    fn complete(&mut self, name: &str) {
+        if let Some(index) = self.compiling.iter().position(|unit| unit == name) {
+            self.compiling.remove(index);
+        }
+
+        self.last_built = Some(name.to_string());
+
+        self.completed += 1;
+
+        if self.completed > self.bar.length() && self.bar.length() > 0 {
+            self.bar.set_length(self.completed);
+        }
+
+        self.bar.set_position(self.completed);
+        self.update_message();
+    }
+
+    fn update_message(&self) {
+        if self.compiling.is_empty() {
+            let message = self.last_built.as_deref().unwrap_or_default();
+
+            self.bar.set_message(message.bright_black().to_string());
+            return;
+        }
+
+        let mut message = self
+            .compiling
+            .iter()
+            .take(SHOWN_UNITS)
+            .map(|unit| unit.bright_yellow().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if self.compiling.len() > SHOWN_UNITS {
+            let _ = write!(message, " +{}", self.compiling.len() - SHOWN_UNITS);
+        }
+
+        self.bar.set_message(message);
+    }
+
+    /// Handles a status line of cargo, eg. `Compiling serde v1.0.0`, returns `false` for other lines
+    ///
+    /// The format of these lines is not stable, lines that are not recognized are printed as is.
+    fn status(&mut self, line: &str) -> bool {
+        let stripped = strip_ansi(line);
+
+        // Status lines are indented so that their verb is right-aligned
+        if !stripped.starts_with(' ') {
+            return false;
+        }
+
+        let mut words = stripped.split_whitespace();
+
+        match (words.next(), words.next()) {
+            (Some("Compiling" | "Checking" | "Documenting"), Some(name)) => {
+                self.compiling.push(name.to_string());
+                self.update_message();
+            }
+            (Some("Finished" | "Running"), _) => {}
+            (Some(verb), Some(_)) if verb.chars().all(|char| char.is_ascii_alphabetic()) => {
+                self.bar.set_prefix(verb.to_string());
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    pub fn handle(&mut self, event: CargoEvent) {
+        match event {
+            CargoEvent::Artifact(artifact) => {
+                if artifact.fresh {
+                    self.fresh += 1;
+                }
+
+                self.bar.set_prefix("Building");
+                self.complete(artifact.package_id.name());
+            }
+            CargoEvent::BuildScript(script) => {
+                self.complete(script.package_id.name());
+            }
+            CargoEvent::Message(message) => {
+                match message.message.level.as_str() {
+                    "warning" => self.warnings += 1,
+                    "error" => self.errors += 1,
+                    _ => {}
+                }
+
+                if let Some(rendered) = &message.message.rendered {
+                    self.bar.println(rendered.trim_end());
+                }
+            }
+            CargoEvent::Stderr(line) => {
+                if !self.status(&line) {
+                    self.bar.println(line);
+                }
+            }
+            CargoEvent::Stdout(line) => self.bar.println(line),
+            CargoEvent::Finished { .. } => {}
+        }
+    }
+
+    /// Clears the display and prints a summary of the build
+    pub fn finish(self, success: bool) {
+        self.bar.finish_and_clear();
+
+        let elapsed = format!("{:.2}s", self.bar.elapsed().as_secs_f64());
+
+        let mut parts = vec![format!("{} units ({} fresh)", self.completed, self.fresh)];
+
+        if self.errors > 0 {
+            parts.push(plural(self.errors, "error").bright_red().to_string());
+        }
+
+        if self.warnings > 0 {
+            parts.push(plural(self.warnings, "warning").bright_yellow().to_string());
+        }
+
+        if let Some((sccache, before)) = &self.sccache {
+            if let Some(after) = SccacheStats::read(sccache) {
+                let hits = after.hits.saturating_sub(before.hits);
+                let misses = after.misses.saturating_sub(before.misses);
+
+                let total = hits + misses;
+
+                if let Some(rate) = (hits * 100).checked_div(total) {
+                    parts.push(format!("sccache {rate}% hits ({hits}/{total})"));
+                }
+            }
+        }
+
+        let summary = parts.join(" · ");
+
+        if success {
+            eprintln!(
+                "🚀 {} in {} · {}",
+                "Finished".bright_green(),
+                elapsed,
+                summary
+            );
+        } else {
+            eprintln!(
+                "❌ {} after {} · {}",
+                "Failed".bright_red(),
+                elapsed,
+                summary
+            );
+        }
+    }
+}
@@ -16,13 +16,16 @@
  */
 
 use crate::cli::app::App;
+use crate::cli::progress::SpinnerStatus;
+use crate::core::invocation::{CargoEvent, CargoInvocation};
 use anyhow::Result;
-use cargo_util::ProcessBuilder;
 use clap::Values;
 use colored::Colorize;
 use comfy_table::ContentArrangement;
 use comfy_table::{modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Cell, Color};
 use indicatif::{ProgressBar, ProgressStyle};
+use std::io::IsTerminal;
+use std::process::ExitStatus;
 use std::thread::JoinHandle;
 
 use serde::{Deserialize, Serialize};
@@ -63,112 +66,89 @@ pub struct Function {
     pub size: i64,
 }
 
+/// Builds the project with progress on the spinner, exits with the errors of the build if it fails
+///
+/// `cargo bloat` builds the same profile, it then finds every artifact fresh and only analyses them.
+fn build(spinner: &ProgressBar) {
+    let mut status = SpinnerStatus::default();
+    let mut errors: Vec<String> = vec![];
+
+    let format = if std::io::stderr().is_terminal() {
+        "json-diagnostic-rendered-ansi"
+    } else {
+        "json"
+    };
+
+    let built = CargoInvocation::new("build")
+        .message_format(Some(format))
+        .capture_stderr(true)
+        .run(|event| {
+            if let Some(message) = status.message(&event) {
+                spinner.set_message(message);
+            }
+
+            match event {
+                CargoEvent::Message(message) if message.message.level == "error" => {
+                    errors.extend(message.message.rendered);
+                }
+                CargoEvent::Stderr(line) => errors.push(line),
+                _ => {}
+            }
+        });
+
+    if !built.as_ref().is_ok_and(ExitStatus::success) {
+        spinner.finish_and_clear();
+
+        // Show all errors in the codebase:
+        for error in &errors {
+            eprintln!("{}", error.trim_end());
+        }
+
+        std::process::exit(1);
+    }
+}
+
 /// Panics:
 pub fn run(_app: App, _args: Option<Values>) -> Result<()> {
     let mut handles: Vec<JoinHandle<comfy_table::Table>> = vec![];
     let spinner = ProgressBar::new_spinner();
 
+    spinner.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}"));
+
+    spinner.set_message("Initializing".bright_green().to_string());
+
+    spinner.enable_steady_tick(10);
+
+    // Both analyses start once the project is built, so that neither of them compiles it
+    build(&spinner);
+
     handles.push(std::thread::spawn({
         let spinner = spinner;
         move || -> comfy_table::Table {
-            spinner.set_style(ProgressStyle::default_spinner().template("{spinner} {msg}"));
-
-            spinner.set_message("Initializing".bright_green().to_string());
-
-            spinner.enable_steady_tick(10);
-
-            let mut warning_count: u64 = 0;
-            let mut error_count: u64 = 0;
-
-            // Run cargo bloat
-            let mut command = ProcessBuilder::new("cargo");
-
-            command
-                .arg("bloat")
-                .arg("--crates")
-                .arg("--message-format=json");
-
-            let output = command.exec_with_streaming(
-                &mut |_| Ok(()),
-                &mut |on_stderr| {
-                    let contents = on_stderr.trim().to_string();
-
-                    if !contents.is_empty() {
-                        let chunks: Vec<&str> = contents.split(' ').collect();
-
-                        if contents.starts_with("Downloaded") {
-                            let name = chunks[1].to_string();
-
-                            let mut version = chunks[2].to_string();
-
-                            if version.starts_with('v') {
-                                version.remove(0);
-                            }
-
-                            spinner.set_message(format!(
-                                "{} ({}{}{})",
-                                "Download".bright_cyan(),
-                                name.bright_yellow(),
-                                "@".bright_magenta(),
-                                version.bright_black(),
-                            ));
-                        }
-
-                        if contents.starts_with("Compiling") {
-                            let name = chunks[1].to_string();
-
-                            let mut version = chunks[2].to_string();
-
-                            if version.starts_with('v') {
-                                version.remove(0);
-                            }
-
-                            spinner.set_message(format!(
-                                "{} ({}{}{})",
-                                "Compile".bright_cyan(),
-                                name.bright_yellow(),
-                                "@".bright_magenta(),
-                                version.bright_black(),
-                            ));
-                        }
-
-                        if contents.starts_with("warning:") {
-                            warning_count += 1;
-                            spinner.set_message(format!(
-                                "{} ({} {}, {} {})",
-                                "Check".bright_cyan(),
-                                warning_count.to_string().bright_magenta(),
-                                "warnings".bright_yellow(),
-                                error_count.to_string().bright_red(),
-                                "errors".bright_yellow(),
-                            ));
-                        }
-
-                        if contents.starts_with("error") {
-                            error_count += 1;
-                        }
-                    }
-
-                    Ok(())
-                },
-                true,
-            );
-
-            if output.is_err() {
-                spinner.finish();
-
-                // Show all errors in the codebase:
-                std::process::Command::new("cargo")
-                    .arg("check")
-                    .status()
-                    .unwrap();
+            spinner.set_message("Analysing".bright_cyan().to_string());
 
-                std::process::exit(1);
-            }
+            let mut stdout = String::new();
+            let mut stderr: Vec<String> = vec![];
 
-            spinner.set_message("Analysing".bright_cyan().to_string());
+            let output = CargoInvocation::new("bloat")
+                .args(["--crates", "--message-format=json"])
+                .message_format(None)
+                .capture_stderr(true)
+                .run(|event| match event {
+                    CargoEvent::Stdout(line) => stdout.push_str(&line),
+                    CargoEvent::Stderr(line) => stderr.push(line),
+                    _ => {}
+                });
 
-            let stdout = String::from_utf8(output.unwrap().stdout).unwrap();
+            if !output.as_ref().is_ok_and(ExitStatus::success) {
+                spinner.finish_and_clear();
+
+                for line in &stderr {
+                    eprintln!("{line}");
+                }
+
+                std::process::exit(1);
+            }
 
             let data = serde_json::from_str::<BloatCrateAnalysis>(&stdout).unwrap();
 
@@ -201,14 +181,19 @@ pub fn run(_app: App, _args: Option<Values>) -> Result<()> {
     }));
 
     handles.push(std::thread::spawn(move || -> comfy_table::Table {
-        let output = std::process::Command::new("cargo")
-            .arg("bloat")
+        let mut stdout = String::new();
+
+        CargoInvocation::new("bloat")
             .arg("--message-format=json")
-            .output()
+            .message_format(None)
+            .capture_stderr(true)
+            .run(|event| {
+                if let CargoEvent::Stdout(line) = event {
+                    stdout.push_str(&line);
+                }
+            })
             .unwrap();
 
-        let stdout = String::from_utf8(output.stdout).unwrap();
-
         let data = serde_json::from_str::<BloatFunctionAnalysis>(&stdout).unwrap();
 
         let mut function_table = comfy_table::Table::new();
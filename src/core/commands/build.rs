@@ -16,18 +16,80 @@
  */
 
 use crate::cli::app::App;
-use crate::core::config::enable::enable_fleet;
+use crate::cli::progress::{is_plain, BuildProgress};
+use crate::core::config::enable::{enable_fleet, requested_targets};
+use crate::core::config::managed::ManagedManifest;
+use crate::core::config::toolchain::is_nightly;
+use crate::core::config::FleetConfig;
+use crate::core::invocation::{unit_count, CargoInvocation};
+use crate::core::ramdisk::{format_size, snapshot};
+use crate::utils::process::exit_with;
+use ansi_term::Colour::Yellow;
 use anyhow::Result;
 use clap::Values;
+use std::process::ExitStatus;
 
-pub fn run(app: App, args: Option<Values>) -> Result<()> {
-    enable_fleet(app);
+/// Runs `cargo build` with the progress display, or with the output of cargo as is when `plain`
+pub fn cargo_build(args: &[&str], plain: bool, config: &FleetConfig) -> Result<ExitStatus> {
+    let mut invocation = CargoInvocation::new("build");
 
-    let args = args.unwrap_or_default();
-    std::process::Command::new("cargo")
-        .arg("build")
-        .args(args)
-        .status()?;
+    invocation.args(args.iter().copied());
+
+    if is_plain(plain, args) {
+        return invocation.run(|event| event.print());
+    }
+
+    let target = requested_targets(args)
+        .into_iter()
+        .next()
+        .or_else(|| rustc_version::version_meta().ok().map(|meta| meta.host));
+
+    let mut progress = BuildProgress::new(
+        unit_count(args, target.as_deref(), is_nightly()),
+        config.build.sccache.as_deref(),
+    );
+
+    // Diagnostics are printed by the display, above the progress bar
+    invocation
+        .message_format(Some("json-diagnostic-rendered-ansi"))
+        .capture_stderr(true);
+
+    if !args.iter().any(|arg| arg.starts_with("--color")) {
+        invocation.arg("--color=always");
+    }
+
+    let status = invocation.run(|event| progress.handle(event))?;
+
+    progress.finish(status.success());
+
+    Ok(status)
+}
+
+/// Builds the project, then saves a snapshot of its ramdisk when `rd_snapshot` is set
+///
+/// Fleet exits with the status of cargo when the build fails.
+pub fn run(mut app: App, args: Option<Values>, plain: bool) -> Result<()> {
+    let args: Vec<&str> = args.unwrap_or_default().collect();
+    let config = app.config_or_create()?;
+    let root_dir = app.root_dir.clone();
+
+    enable_fleet(app, &requested_targets(&args));
+
+    let status = cargo_build(&args, plain, &config)?;
+
+    if status.success() && config.rd_snapshot {
+        if let Some(ramdisk) = ManagedManifest::load(&root_dir)?.ramdisk {
+            // The build succeeded, a snapshot that cannot be saved is only worth a warning
+            match snapshot(&ramdisk, &config.fleet_id) {
+                Ok(size) => println!("💾 Saved a snapshot of the ramdisk ({})", format_size(size)),
+                Err(err) => eprintln!("{}: {:#}", Yellow.paint("warning"), err),
+            }
+        }
+    }
+
+    if !status.success() {
+        exit_with(status);
+    }
 
     Ok(())
 }
@@ -0,0 +1,353 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use crate::cli::app::App;
+use crate::cli::prompt::prompts::Confirm;
+use crate::core::config::error::ConfigError;
+use crate::core::config::global::{self, FleetGlobalConfig};
+use crate::core::config::layered::{check_key, parse_value, LayeredConfig, Origin};
+use crate::core::config::migrate::migrate;
+use crate::core::config::{FleetConfig, CONFIG_FILE, KEYS};
+use anyhow::{anyhow, bail, Context, Result};
+use colored::Colorize;
+use schemars::schema_for;
+use std::borrow::Cow;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use toml::Value;
+use toml_edit::{table, Document, Item};
+
+/// Subcommands of `fleet config`
+#[derive(Debug, Clone)]
+pub enum ConfigAction {
+    Get {
+        key: String,
+        show_origin: bool,
+    },
+    Set {
+        key: String,
+        value: String,
+        global: bool,
+    },
+    Unset {
+        key: String,
+        global: bool,
+    },
+    Edit {
+        global: bool,
+    },
+    List {
+        show_origin: bool,
+    },
+    Schema {
+        global: bool,
+    },
+}
+
+/// Renders a value, strings are printed without quotes
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn print_value(key: Option<&str>, value: &Value, origin: &Origin, show_origin: bool) {
+    let value = match key {
+        Some(key) => format!("{key} = {value}"),
+        None => display_value(value),
+    };
+
+    if show_origin {
+        println!("{}\t{}", origin.to_string().bright_black(), value);
+    } else {
+        println!("{value}");
+    }
+}
+
+/// Prints the effective value of `key`
+fn get(layers: &LayeredConfig, key: &str, show_origin: bool) -> Result<()> {
+    check_key(key, KEYS)?;
+
+    let Some((value, origin)) = layers.get(key) else {
+        bail!("`{key}` is not set");
+    };
+
+    print_value(None, value, origin, show_origin);
+
+    Ok(())
+}
+
+/// Prints every value that is set
+fn list(layers: &LayeredConfig, show_origin: bool) {
+    for (key, value, origin) in layers.values() {
+        print_value(Some(&key), value, origin, show_origin);
+    }
+}
+
+/// A config file edited by `fleet config`
+pub(crate) struct ConfigFile {
+    path: PathBuf,
+    global: bool,
+}
+
+impl ConfigFile {
+    /// The `fleet.toml` of the project root, or the global config file with `global`
+    pub(crate) fn open(root_dir: &Path, global: bool) -> Result<Self> {
+        if global {
+            let path = FleetGlobalConfig::path()?;
+
+            // Creates the global config file if it does not exist yet, an existing file is only
+            // validated once it is written so that it can be repaired
+            if !path.exists() {
+                FleetGlobalConfig::run_config()?;
+            }
+
+            return Ok(Self { path, global });
+        }
+
+        let path = root_dir.join(CONFIG_FILE);
+
+        if !path.exists() {
+            bail!("no fleet.toml found, run `fleet init` first");
+        }
+
+        // Keys are written in the current layout, an invalid `schema_version` is reported once
+        // the config is loaded so that it can be repaired here
+        let _ = migrate(&path);
+
+        Ok(Self { path, global })
+    }
+
+    fn keys(&self) -> &'static [&'static str] {
+        if self.global {
+            global::KEYS
+        } else {
+            KEYS
+        }
+    }
+
+    fn read(&self) -> Result<String> {
+        std::fs::read_to_string(&self.path)
+            .with_context(|| format!("failed to read {}", self.path.display()))
+    }
+
+    fn document(&self) -> Result<Document> {
+        self.read()?
+            .parse::<Document>()
+            .map_err(|err| anyhow!("failed to parse {}: {err}", self.path.display()))
+    }
+
+    /// Checks `contents` against the schema of the file
+    fn validate(&self, contents: &str) -> Result<()> {
+        let result = if self.global {
+            toml::from_str::<FleetGlobalConfig>(contents).map(|_| ())
+        } else {
+            toml::from_str::<FleetConfig>(contents).map(|_| ())
+        };
+
+        result.map_err(|err| ConfigError::from_toml(&self.path, contents, &err).into())
+    }
+
+    /// Validates the document and writes it, the file is left untouched if it is invalid
+    fn write(&self, document: &Document) -> Result<()> {
+        let contents = document.to_string();
+
+        self.validate(&contents)?;
+
+        std::fs::write(&self.path, contents)
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+}
+
+/// Splits a dotted key into its tables and its name
+fn split_key(key: &str) -> (Vec<&str>, &str) {
+    match key.rsplit_once('.') {
+        Some((tables, name)) => (tables.split('.').collect(), name),
+        None => (Vec::new(), key),
+    }
+}
+
+/// Writes `key` into the config file
+pub(crate) fn set(file: &ConfigFile, key: &str, value: &str) -> Result<()> {
+    check_key(key, file.keys())?;
+
+    let mut document = file.document()?;
+
+    let value = parse_value(value);
+    let edit_value = value
+        .to_string()
+        .parse::<toml_edit::Value>()
+        .with_context(|| format!("invalid value for `{key}`"))?;
+
+    let (tables, name) = split_key(key);
+    let mut item = document.as_item_mut();
+
+    for part in tables {
+        if item.get(part).is_none() {
+            item[part] = table();
+        }
+
+        item = &mut item[part];
+    }
+
+    item[name] = Item::Value(edit_value);
+
+    file.write(&document)?;
+
+    println!("{key} = {value}");
+
+    Ok(())
+}
+
+/// Removes `key` from the config file
+///
+/// A file that is already invalid is written without validation, removing the key may be the fix.
+fn unset(file: &ConfigFile, key: &str) -> Result<()> {
+    check_key(key, file.keys())?;
+
+    let valid = file.validate(&file.read()?).is_ok();
+    let mut document = file.document()?;
+
+    let (tables, name) = split_key(key);
+    let mut item = Some(document.as_item_mut());
+
+    for part in tables {
+        item = item.and_then(|item| item.get_mut(part));
+    }
+
+    let removed = item
+        .and_then(Item::as_table_like_mut)
+        .and_then(|table| table.remove(name));
+
+    if removed.is_none() {
+        bail!("`{key}` is not set in {}", file.path.display());
+    }
+
+    if valid {
+        file.write(&document)?;
+    } else {
+        std::fs::write(&file.path, document.to_string())
+            .with_context(|| format!("failed to write {}", file.path.display()))?;
+    }
+
+    println!("Removed {key}");
+
+    Ok(())
+}
+
+/// Returns the editor set in `VISUAL` or `EDITOR`
+fn editor() -> String {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| {
+            if cfg!(windows) {
+                String::from("notepad")
+            } else {
+                String::from("vi")
+            }
+        })
+}
+
+/// Opens the config file in the editor of the user
+///
+/// Invalid changes are reported and the file can be edited again, or restored.
+fn edit(file: &ConfigFile) -> Result<()> {
+    let original = file.read()?;
+    let editor = editor();
+    let mut parts = editor.split_whitespace();
+
+    let Some(program) = parts.next() else {
+        bail!("no editor set, set the `EDITOR` environment variable");
+    };
+
+    let args = parts.collect::<Vec<&str>>();
+
+    loop {
+        let status = Command::new(program)
+            .args(&args)
+            .arg(&file.path)
+            .status()
+            .with_context(|| format!("failed to run `{editor}`"))?;
+
+        if !status.success() {
+            bail!("`{editor}` exited with {status}");
+        }
+
+        let Err(err) = file.validate(&file.read()?) else {
+            return Ok(());
+        };
+
+        eprintln!("{}: {:#}", "error".bright_red(), err);
+
+        let confirm = Confirm {
+            message: Cow::Borrowed("Edit the file again?"),
+            default: true,
+        };
+
+        // The prompt can only be answered from a terminal
+        if !std::io::stdin().is_terminal() || !confirm.run()? {
+            std::fs::write(&file.path, original)
+                .with_context(|| format!("failed to restore {}", file.path.display()))?;
+
+            bail!("the changes to {} were discarded", file.path.display());
+        }
+    }
+}
+
+/// Prints the JSON Schema of `fleet.toml`, or of the global config file with `global`
+///
+/// The schema is generated from the config types, eg. for Taplo or Even Better TOML.
+fn schema(global: bool) -> Result<()> {
+    let schema = if global {
+        schema_for!(FleetGlobalConfig)
+    } else {
+        schema_for!(FleetConfig)
+    };
+
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+
+    Ok(())
+}
+
+/// Reads or writes the fleet config
+pub fn run(app: App, action: ConfigAction) -> Result<()> {
+    let App {
+        current_dir,
+        root_dir,
+        overrides,
+        ..
+    } = app;
+
+    let layers = || LayeredConfig::load(&root_dir, &current_dir, &overrides);
+
+    match action {
+        ConfigAction::Get { key, show_origin } => get(&layers()?, &key, show_origin),
+        ConfigAction::Set { key, value, global } => {
+            set(&ConfigFile::open(&root_dir, global)?, &key, &value)
+        }
+        ConfigAction::Unset { key, global } => unset(&ConfigFile::open(&root_dir, global)?, &key),
+        ConfigAction::Edit { global } => edit(&ConfigFile::open(&root_dir, global)?),
+        ConfigAction::List { show_origin } => {
+            list(&layers()?, show_origin);
+
+            Ok(())
+        }
+        ConfigAction::Schema { global } => schema(global),
+    }
+}
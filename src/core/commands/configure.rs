@@ -17,18 +17,23 @@
 
 use crate::cli::app::App;
 use crate::cli::prompt;
+use crate::core::config::{FleetConfig, Linker};
 use anyhow::Result;
-use clap::Values;
 use colored::Colorize;
+use std::path::Path;
 
+/// Prompts for a linker, installs it and saves it into the `fleet.toml` in `root_dir`
+///
+/// The paths of the installed binaries are saved with it, the global config only holds the tools
+/// found when it was created.
 ///
 /// # Panics
 ///
 /// can panic is fails to run
-pub fn run(_app: App, _args: Option<Values>) -> Result<()> {
+pub fn configure_linker(root_dir: &Path, dry_run: bool) -> Result<Linker> {
     let prompt = format!("Select a {}:", "Linker".bright_cyan());
 
-    let linker_options = match std::env::consts::OS {
+    let mut linker_options = match std::env::consts::OS {
         "windows" => {
             vec![std::borrow::Cow::Owned(format!(
                 "🚄 lld - {} faster",
@@ -50,6 +55,10 @@ pub fn run(_app: App, _args: Option<Values>) -> Result<()> {
         &_ => Vec::new(),
     };
 
+    linker_options.push(std::borrow::Cow::Owned(String::from(
+        "🐢 system - default linker",
+    )));
+
     let select = prompt::prompts::Select {
         message: std::borrow::Cow::Borrowed(prompt.as_str()),
         paged: false,
@@ -59,7 +68,29 @@ pub fn run(_app: App, _args: Option<Values>) -> Result<()> {
 
     let linker_selected = linker_options[select.run().unwrap()].to_string();
 
-    crate::utils::configure::install_linker(&linker_selected);
+    let linker = if linker_selected.contains("mold") {
+        Linker::Mold
+    } else if linker_selected.contains("zld") {
+        Linker::Zld
+    } else if linker_selected.contains("lld") {
+        Linker::Lld
+    } else {
+        Linker::System
+    };
+
+    let paths = crate::utils::configure::install_linker(linker, dry_run)?;
+
+    if !dry_run {
+        FleetConfig::save_linker(root_dir, linker, &paths)?;
+    }
+
+    Ok(linker)
+}
+
+pub fn run(app: App, dry_run: bool) -> Result<()> {
+    let App { root_dir, .. } = app;
+
+    configure_linker(&root_dir, dry_run)?;
 
     Ok(())
 }
@@ -0,0 +1,442 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use crate::cli::app::App;
+use crate::core::config::cargo::{
+    generate_config, host_triple, outdated_keys, LinkerStrategy, Tools,
+};
+use crate::core::config::managed::ManagedManifest;
+use crate::core::config::{global::FleetGlobalConfig, toolchain::is_nightly};
+use crate::core::config::{FleetConfig, Linker, CONFIG_FILE};
+use crate::core::ramdisk::{format_size, ramdisk_dir, RESERVED_SPACE};
+use crate::utils::disk::{available_space, dir_size, disk_kind, DiskKind};
+use anyhow::Result;
+use colored::Colorize;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// State of a tool configured in `fleet.toml`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ToolStatus {
+    Ok,
+    NotConfigured,
+    Missing,
+    NotExecutable,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolchainReport {
+    pub channel: Option<String>,
+    pub version: Option<String>,
+    pub nightly: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolReport {
+    pub name: String,
+    pub path: Option<PathBuf>,
+    pub status: ToolStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CargoConfigReport {
+    pub path: Option<PathBuf>,
+    pub up_to_date: bool,
+    pub outdated_keys: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RamdiskReport {
+    pub enabled: bool,
+    pub path: Option<PathBuf>,
+    pub available_bytes: Option<u64>,
+    /// Size of the ramdisk of the project, even when it is not used
+    pub used_bytes: Option<u64>,
+    /// `rd_size_limit` in bytes
+    pub size_limit_bytes: Option<u64>,
+    /// Storage holding the project
+    pub disk: DiskKind,
+}
+
+/// Result of every check run by `fleet doctor`
+#[derive(Debug, Serialize)]
+pub struct DoctorReport {
+    /// Path of the `fleet.toml` of the project, `None` if the project is not initialized
+    pub fleet_toml: Option<PathBuf>,
+    pub toolchain: ToolchainReport,
+    pub tools: Vec<ToolReport>,
+    pub cargo_config: CargoConfigReport,
+    pub ramdisk: RamdiskReport,
+    pub fixes: Vec<String>,
+}
+
+/// Checks whether a tool exists and can be executed
+fn tool_status(path: Option<&Path>) -> ToolStatus {
+    let Some(path) = path else {
+        return ToolStatus::NotConfigured;
+    };
+
+    // `which` only resolves paths that are executable
+    if which::which(path).is_ok() {
+        ToolStatus::Ok
+    } else if path.exists() {
+        ToolStatus::NotExecutable
+    } else {
+        ToolStatus::Missing
+    }
+}
+
+/// Returns the tools used by the linker strategy of the host, only `sccache` when it is unknown
+fn host_tools(strategy: Option<LinkerStrategy>) -> &'static [&'static str] {
+    match strategy {
+        Some(LinkerStrategy::ClangLld) => &["sccache", "lld", "clang", "mold"],
+        Some(LinkerStrategy::RustLld) => &["sccache", "lld"],
+        Some(LinkerStrategy::Zld) => &["sccache", "lld", "zld"],
+        None => &["sccache"],
+    }
+}
+
+fn check_toolchain() -> ToolchainReport {
+    let meta = rustc_version::version_meta().ok();
+
+    ToolchainReport {
+        channel: meta
+            .as_ref()
+            .map(|meta| format!("{:?}", meta.channel).to_lowercase()),
+        version: meta.map(|meta| meta.short_version_string),
+        nightly: is_nightly(),
+    }
+}
+
+fn check_cargo_config(
+    root_dir: &Path,
+    config: &FleetConfig,
+    ramdisk: Option<&Path>,
+    nightly: bool,
+) -> CargoConfigReport {
+    let cargo_dir = root_dir.join(".cargo");
+
+    let path = [cargo_dir.join("config.toml"), cargo_dir.join("config")]
+        .into_iter()
+        .find(|path| path.exists());
+
+    let contents = path
+        .as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .unwrap_or_default();
+
+    let generated = generate_config(
+        &Tools::from(&config.build),
+        &[],
+        &config.presets(),
+        ramdisk,
+        nightly,
+    );
+
+    let outdated_keys = outdated_keys(&contents, &generated);
+
+    CargoConfigReport {
+        up_to_date: path.is_some() && outdated_keys.is_empty(),
+        path,
+        outdated_keys,
+    }
+}
+
+fn check_ramdisk(root_dir: &Path, config: &FleetConfig, ramdisk: Option<&Path>) -> RamdiskReport {
+    RamdiskReport {
+        disk: disk_kind(root_dir),
+        enabled: config.rd_enabled,
+        available_bytes: ramdisk.and_then(available_space),
+        used_bytes: Some(ramdisk_dir(&config.fleet_id))
+            .filter(|dir| config.rd_enabled && !config.fleet_id.is_empty() && dir.is_dir())
+            .map(|dir| dir_size(&dir)),
+        size_limit_bytes: config.rd_size_limit(),
+        path: ramdisk.map(Path::to_path_buf),
+    }
+}
+
+/// Suggests a fix for every failing check
+fn fixes(report: &DoctorReport, linker: Option<Linker>) -> Vec<String> {
+    let mut fixes = Vec::new();
+
+    if report.fleet_toml.is_none() {
+        fixes.push(String::from("Run `fleet init` to create fleet.toml"));
+    }
+
+    if !report.toolchain.nightly {
+        fixes.push(String::from(
+            "Set `toolchain = \"nightly\"` in fleet.toml to enable nightly-only optimizations",
+        ));
+    }
+
+    for tool in &report.tools {
+        match (tool.name.as_str(), tool.status) {
+            (_, ToolStatus::Ok) => {}
+            ("sccache", ToolStatus::NotConfigured | ToolStatus::Missing) => {
+                fixes.push(String::from("Install sccache with `cargo install sccache`"));
+            }
+            (name, ToolStatus::NotExecutable) => {
+                fixes.push(format!("Make the {name} binary executable"));
+            }
+            (name, ToolStatus::Missing) => {
+                fixes.push(format!(
+                    "Install {name} or remove its path from fleet.toml, `fleet configure` can install a linker"
+                ));
+            }
+            (name, ToolStatus::NotConfigured) => {
+                if linker.map(Linker::as_str) == Some(name) {
+                    fixes.push(format!("Run `fleet configure` to install {name}"));
+                }
+            }
+        }
+    }
+
+    if !report.cargo_config.up_to_date {
+        fixes.push(String::from(
+            "Run `fleet build` to regenerate the cargo config",
+        ));
+    }
+
+    let over_limit = matches!(
+        (report.ramdisk.used_bytes, report.ramdisk.size_limit_bytes),
+        (Some(used), Some(limit)) if used > limit
+    );
+
+    if over_limit {
+        fixes.push(String::from(
+            "The ramdisk is over `rd_size_limit` so the project builds on disk, run `cargo clean` or raise the limit",
+        ));
+    } else if report.ramdisk.enabled && report.ramdisk.path.is_none() {
+        fixes.push(String::from("Run `fleet build` to set up the ramdisk"));
+    } else if !report.ramdisk.enabled && report.ramdisk.disk.is_slow() {
+        fixes.push(format!(
+            "Run `fleet ramdisk enable`, building on a ramdisk is faster than on this {}",
+            report.ramdisk.disk
+        ));
+    }
+
+    if let Some(available) = report.ramdisk.available_bytes {
+        // Builds move to disk once the ramdisk cannot grow by the reserved space
+        if available < RESERVED_SPACE {
+            fixes.push(format!(
+                "Less than {} is free on the ramdisk, run `cargo clean` to free some space",
+                format_size(RESERVED_SPACE)
+            ));
+        }
+    }
+
+    fixes
+}
+
+fn check(status: bool) -> colored::ColoredString {
+    if status {
+        "✔".bright_green()
+    } else {
+        "✘".bright_red()
+    }
+}
+
+/// Marks a supported setup that can be improved
+fn warn() -> colored::ColoredString {
+    "!".bright_yellow()
+}
+
+fn print_report(report: &DoctorReport) {
+    println!("🩺 {}\n", "Fleet Doctor".bright_cyan());
+
+    println!("{}", "Config".bright_yellow());
+    match &report.fleet_toml {
+        Some(path) => println!("  {} {}", check(true), path.display()),
+        None => println!("  {} no fleet.toml found", check(false)),
+    }
+
+    println!("{}", "Toolchain".bright_yellow());
+    // Stable toolchains are supported, nightly only enables more optimizations
+    let marker = if report.toolchain.nightly {
+        check(true)
+    } else {
+        warn()
+    };
+
+    println!(
+        "  {} {} ({})",
+        marker,
+        report.toolchain.channel.as_deref().unwrap_or("unknown"),
+        report
+            .toolchain
+            .version
+            .as_deref()
+            .unwrap_or("rustc not found")
+            .bright_black()
+    );
+
+    println!("{}", "Tools".bright_yellow());
+    for tool in &report.tools {
+        let status = match tool.status {
+            ToolStatus::Ok => String::from("ok"),
+            ToolStatus::NotConfigured => String::from("not configured"),
+            ToolStatus::Missing => String::from("not found"),
+            ToolStatus::NotExecutable => String::from("not executable"),
+        };
+
+        let path = tool
+            .path
+            .as_ref()
+            .map(|path| format!(" ({})", path.display()))
+            .unwrap_or_default();
+
+        let marker = if tool.status == ToolStatus::NotConfigured {
+            "-".bright_black()
+        } else {
+            check(tool.status == ToolStatus::Ok)
+        };
+
+        println!(
+            "  {} {:<8} {}{}",
+            marker,
+            tool.name,
+            status,
+            path.bright_black()
+        );
+    }
+
+    println!("{}", "Cargo config".bright_yellow());
+    match &report.cargo_config.path {
+        Some(path) if report.cargo_config.up_to_date => {
+            println!("  {} {} is up to date", check(true), path.display());
+        }
+        Some(path) => {
+            println!(
+                "  {} {} differs from the generated config: {}",
+                check(false),
+                path.display(),
+                report.cargo_config.outdated_keys.join(", ").bright_black()
+            );
+        }
+        None => println!("  {} no cargo config found", check(false)),
+    }
+
+    println!("{}", "Ramdisk".bright_yellow());
+    match &report.ramdisk.path {
+        Some(path) => {
+            let available = report
+                .ramdisk
+                .available_bytes
+                .map_or_else(|| String::from("unknown"), format_size);
+
+            let used = match (report.ramdisk.used_bytes, report.ramdisk.size_limit_bytes) {
+                (Some(used), Some(limit)) => {
+                    format!("{} of {} used, ", format_size(used), format_size(limit))
+                }
+                (Some(used), None) => format!("{} used, ", format_size(used)),
+                _ => String::new(),
+            };
+
+            println!(
+                "  {} target → {} ({}{} free)",
+                check(true),
+                path.display(),
+                used,
+                available
+            );
+        }
+        None => println!("  {} target is not on a ramdisk", "-".bright_black()),
+    }
+    println!(
+        "  {} project on {}",
+        "-".bright_black(),
+        report.ramdisk.disk
+    );
+
+    if !report.fixes.is_empty() {
+        println!("\n{}", "Fixes".bright_yellow());
+
+        for fix in &report.fixes {
+            println!("  • {fix}");
+        }
+    }
+}
+
+/// Reports whether fleet is correctly set up for the project
+pub fn run(mut app: App, json: bool) -> Result<()> {
+    app.load_config()?;
+
+    let App {
+        config, root_dir, ..
+    } = app;
+
+    let fleet_toml = config.as_ref().map(|_| root_dir.join(CONFIG_FILE));
+
+    // Tools of the global config are checked until the project is initialized
+    let config = if let Some(config) = config {
+        config
+    } else {
+        FleetConfig {
+            build: FleetGlobalConfig::run_config()?.build.into(),
+            ..FleetConfig::new()
+        }
+    };
+
+    let build = &config.build;
+    let used = host_tools(
+        host_triple()
+            .as_deref()
+            .and_then(LinkerStrategy::for_triple),
+    );
+
+    let tools = [
+        ("sccache", &build.sccache),
+        ("lld", &build.lld),
+        ("clang", &build.clang),
+        ("zld", &build.zld),
+        ("mold", &build.mold),
+    ]
+    .into_iter()
+    .filter(|(name, _)| used.contains(name))
+    .map(|(name, path)| ToolReport {
+        name: name.to_string(),
+        status: tool_status(path.as_deref()),
+        path: path.clone(),
+    })
+    .collect();
+
+    let ramdisk = ManagedManifest::load(&root_dir)?.ramdisk;
+
+    let toolchain = check_toolchain();
+    let cargo_config =
+        check_cargo_config(&root_dir, &config, ramdisk.as_deref(), toolchain.nightly);
+
+    let mut report = DoctorReport {
+        fleet_toml,
+        toolchain,
+        tools,
+        cargo_config,
+        ramdisk: check_ramdisk(&root_dir, &config, ramdisk.as_deref()),
+        fixes: Vec::new(),
+    };
+
+    report.fixes = fixes(&report, build.linker);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+
+    Ok(())
+}
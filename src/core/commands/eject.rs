@@ -0,0 +1,125 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use crate::cli::app::App;
+use crate::core::config::managed::{restore_key, ManagedManifest, MANIFEST_FILE};
+use crate::core::config::read_fleet_id;
+use crate::core::config::toolchain::TOOLCHAIN_FILE;
+use crate::core::ramdisk::{linked_ramdisk, remove_snapshot, restore_target};
+use anyhow::{Context, Result};
+use clap::Values;
+use colored::Colorize;
+use toml_edit::Document;
+
+/// Reverts every change fleet has made to the project
+///
+/// Only the keys recorded in `fleet-managed.toml` are removed from the cargo config, keys that
+/// existed before fleet overwrote them are restored to their original value. The `target`
+/// symlink to a ramdisk is restored even without the manifest, older versions did not write it.
+pub fn run(app: App, _args: Option<Values>) -> Result<()> {
+    let App { root_dir, .. } = app;
+    let linked = linked_ramdisk(&root_dir);
+
+    if !root_dir.join(MANIFEST_FILE).exists() && linked.is_none() {
+        println!("Nothing to eject, fleet has not been enabled in this project");
+        return Ok(());
+    }
+
+    let manifest = ManagedManifest::load(&root_dir)?;
+
+    if let Some(config_path) = &manifest.cargo_config {
+        if config_path.exists() {
+            let contents = std::fs::read_to_string(config_path)
+                .with_context(|| format!("failed to read {}", config_path.display()))?;
+
+            let mut document = contents
+                .parse::<Document>()
+                .with_context(|| format!("failed to parse {}", config_path.display()))?;
+
+            for key in manifest.keys.iter().rev() {
+                restore_key(&mut document, key);
+            }
+
+            let contents = document.to_string();
+
+            if manifest.created_cargo_config && contents.trim().is_empty() {
+                std::fs::remove_file(config_path)
+                    .with_context(|| format!("failed to remove {}", config_path.display()))?;
+
+                // Only removes the `.cargo` directory when nothing else is left in it
+                if let Some(cargo_dir) = config_path.parent() {
+                    let _ = std::fs::remove_dir(cargo_dir);
+                }
+            } else {
+                std::fs::write(config_path, contents)
+                    .with_context(|| format!("failed to write {}", config_path.display()))?;
+            }
+
+            println!("📝 Removed Fleet Config");
+        }
+    }
+
+    let toolchain_file = root_dir.join(TOOLCHAIN_FILE);
+
+    if manifest.created_toolchain_file && toolchain_file.exists() {
+        std::fs::remove_file(&toolchain_file)
+            .with_context(|| format!("failed to remove {}", toolchain_file.display()))?;
+
+        println!("📌 Removed pinned toolchain");
+    } else if let Some(key) = manifest
+        .toolchain
+        .as_ref()
+        .filter(|_| toolchain_file.exists())
+    {
+        let contents = std::fs::read_to_string(&toolchain_file)
+            .with_context(|| format!("failed to read {}", toolchain_file.display()))?;
+
+        let mut document = contents
+            .parse::<Document>()
+            .with_context(|| format!("failed to parse {}", toolchain_file.display()))?;
+
+        restore_key(&mut document, key);
+
+        std::fs::write(&toolchain_file, document.to_string())
+            .with_context(|| format!("failed to write {}", toolchain_file.display()))?;
+
+        println!("📌 Restored the pinned toolchain");
+    }
+
+    if let Some(ramdisk) = manifest.ramdisk.as_ref().or(linked.as_ref()) {
+        if restore_target(&root_dir, ramdisk)? {
+            println!("💽 Moved the ramdisk back to target");
+        } else if ramdisk.exists() {
+            println!(
+                "💽 target already holds artifacts, the ramdisk was kept at {}",
+                ramdisk.display()
+            );
+        }
+    }
+
+    if let Some(fleet_id) = read_fleet_id(&root_dir) {
+        if remove_snapshot(&fleet_id)? {
+            println!("💾 Removed the snapshot of the ramdisk");
+        }
+    }
+
+    ManagedManifest::remove(&root_dir)?;
+
+    println!("👋 {}", "Fleet has been ejected".bright_green());
+
+    Ok(())
+}
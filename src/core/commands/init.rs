@@ -16,9 +16,100 @@
  */
 
 use crate::cli::app::App;
-use anyhow::Result;
-use clap::Values;
+use crate::core::commands::configure::configure_linker;
+use crate::core::config::{
+    enable::enable_fleet, find_root, read_fleet_id, FleetConfig, CONFIG_FILE,
+};
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use std::process::Command;
 
-pub fn run(_app: App, _args: Option<Values>) -> Result<()> {
-    todo!()
+/// Template used by `cargo init`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageKind {
+    Bin,
+    Lib,
+}
+
+/// Options of `fleet init`
+#[derive(Debug, Default, Clone)]
+pub struct InitOptions {
+    /// Passed down to `cargo init --bin` or `cargo init --lib`
+    pub kind: Option<PackageKind>,
+    /// Passed down to `cargo init --name`
+    pub name: Option<String>,
+    /// Overwrite an existing `fleet.toml`
+    pub force: bool,
+    /// Run the linker selection prompt
+    pub configure: bool,
+}
+
+/// Initializes a fleet project in the current directory
+///
+/// A cargo package is created unless the directory is already part of a package or workspace,
+/// then `fleet.toml` is generated at the project root with the tools found on the system and the
+/// cargo config is written.
+pub fn run(mut app: App, options: &InitOptions) -> Result<()> {
+    if app.root_dir.join(CONFIG_FILE).exists() && !options.force {
+        bail!("`fleet.toml` already exists, use `--force` to overwrite it");
+    }
+
+    if app.root_dir.join("Cargo.toml").exists() {
+        if options.kind.is_some() || options.name.is_some() {
+            println!(
+                "{}: `Cargo.toml` already exists, `--bin`, `--lib` and `--name` are ignored",
+                "warning".bright_yellow()
+            );
+        }
+
+        println!("📦 Using existing cargo project");
+    } else {
+        let mut command = Command::new("cargo");
+
+        command.arg("init").current_dir(&app.current_dir);
+
+        match options.kind {
+            Some(PackageKind::Bin) => {
+                command.arg("--bin");
+            }
+            Some(PackageKind::Lib) => {
+                command.arg("--lib");
+            }
+            None => {}
+        }
+
+        if let Some(name) = &options.name {
+            command.arg("--name").arg(name);
+        }
+
+        let status = command.status().context("failed to run cargo init")?;
+
+        if !status.success() {
+            bail!("cargo init failed");
+        }
+
+        // The new package can be a member of a workspace
+        app.root_dir = find_root(&app.current_dir).unwrap_or_else(|| app.current_dir.clone());
+    }
+
+    let config_path = app.root_dir.join(CONFIG_FILE);
+    let mut config = FleetConfig::detect();
+
+    // Keeps the ramdisk of the project when overwriting its config, even if it is invalid
+    if let Some(fleet_id) = read_fleet_id(&app.root_dir) {
+        config.fleet_id = fleet_id;
+    }
+
+    std::fs::write(&config_path, toml::to_string(&config)?)
+        .with_context(|| format!("failed to write {}", config_path.display()))?;
+
+    println!("📝 Generated fleet.toml");
+
+    if options.configure {
+        configure_linker(&app.root_dir, false)?;
+    }
+
+    enable_fleet(app, &[]);
+
+    Ok(())
 }
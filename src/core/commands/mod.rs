@@ -17,8 +17,12 @@
 
 pub mod bloat;
 pub mod build;
+pub mod config;
 pub mod configure;
+pub mod doctor;
+pub mod eject;
 pub mod init;
+pub mod ramdisk;
 pub mod run;
 
 pub mod udeps;
@@ -0,0 +1,314 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use crate::cli::app::App;
+use crate::core::commands::config::{set, ConfigFile};
+use crate::core::config::enable::enable_fleet;
+use crate::core::config::layered::Origin;
+use crate::core::config::managed::ManagedManifest;
+use crate::core::ramdisk::{
+    self, format_size, ramdisk_dir, remove_snapshot, restore_target, snapshot, snapshot_dir,
+};
+use crate::utils::disk::{available_space, dir_size};
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use comfy_table::{modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Cell, Color};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Subcommands of `fleet ramdisk`
+#[derive(Debug, Clone, Copy)]
+pub enum RamdiskAction {
+    Status,
+    Enable,
+    Disable,
+    Clean { all: bool },
+    List,
+    Sync,
+}
+
+/// Formats how long ago `time` was, eg. `3 hours ago`
+fn format_age(time: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(time)
+        .map_or(0, |age| age.as_secs());
+
+    let (count, unit) = match seconds {
+        0..=59 => return String::from("just now"),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+
+    if count == 1 {
+        format!("1 {unit} ago")
+    } else {
+        format!("{count} {unit}s ago")
+    }
+}
+
+/// Prints whether the project builds on a ramdisk and how much space it takes
+fn status(app: App) -> Result<()> {
+    let App {
+        config, root_dir, ..
+    } = app;
+
+    let config = config.context("no fleet.toml found, run `fleet init` first")?;
+    let in_use = ManagedManifest::load(&root_dir)?.ramdisk;
+    let ramdisk = ramdisk_dir(&config.fleet_id);
+
+    if config.rd_enabled {
+        println!("{} rd_enabled is set in fleet.toml", "✔".bright_green());
+    } else {
+        println!(
+            "{} rd_enabled is not set, run `fleet ramdisk enable` to build on a ramdisk",
+            "-".bright_black()
+        );
+    }
+
+    match &in_use {
+        Some(path) => println!("{} target → {}", "✔".bright_green(), path.display()),
+        None => println!("{} target is on disk", "-".bright_black()),
+    }
+
+    if ramdisk.is_dir() {
+        let used = match config.rd_size_limit() {
+            Some(limit) => format!(
+                "{} of {}",
+                format_size(dir_size(&ramdisk)),
+                format_size(limit)
+            ),
+            None => format_size(dir_size(&ramdisk)),
+        };
+
+        println!("  Size: {used}");
+    }
+
+    if let Some(available) = available_space(Path::new(ramdisk::RAMDISK_ROOT)) {
+        println!("  Free: {}", format_size(available));
+    }
+
+    if let Some(snapshot) = snapshot_dir(&config.fleet_id).filter(|dir| dir.is_dir()) {
+        println!(
+            "  Snapshot: {} ({})",
+            snapshot.display(),
+            format_size(dir_size(&snapshot))
+        );
+    }
+
+    Ok(())
+}
+
+/// Sets `rd_enabled` in fleet.toml and regenerates the cargo config of the project
+///
+/// Disabling moves the artifacts of the ramdisk back to `target`, unless it already holds some.
+fn toggle(mut app: App, enabled: bool) -> Result<()> {
+    let root_dir = app.root_dir.clone();
+
+    // fleet.toml of the project root is written, later layers would still win
+    if let Some((_, origin)) = app.layers()?.get("rd_enabled") {
+        if !matches!(origin, Origin::Global(_) | Origin::Workspace(_)) {
+            bail!("`rd_enabled` is overridden by {origin}, it cannot be changed in fleet.toml");
+        }
+    }
+
+    set(
+        &ConfigFile::open(&root_dir, false)?,
+        "rd_enabled",
+        &enabled.to_string(),
+    )?;
+
+    app.load_config()?;
+
+    let config = app
+        .config
+        .clone()
+        .context("fleet.toml could not be loaded")?;
+
+    let ramdisk = ManagedManifest::load(&root_dir)?.ramdisk;
+
+    enable_fleet(app, &[]);
+
+    if enabled {
+        return Ok(());
+    }
+
+    if let Some(ramdisk) = &ramdisk {
+        if restore_target(&root_dir, ramdisk)? {
+            println!("💽 Moved the ramdisk back to target");
+        } else if ramdisk.exists() {
+            println!(
+                "💽 target already holds artifacts, run `fleet ramdisk clean` to remove the ramdisk at {}",
+                ramdisk.display()
+            );
+        }
+    }
+
+    if remove_snapshot(&config.fleet_id)? {
+        println!("💾 Removed the snapshot of the ramdisk");
+    }
+
+    Ok(())
+}
+
+/// Removes the ramdisk of the project and its snapshot, or every ramdisk with `all`
+///
+/// Ramdisks of other users are skipped, a ramdisk that cannot be removed is reported and the
+/// others are still removed.
+fn clean(app: App, all: bool) -> Result<()> {
+    let App { config, .. } = app;
+
+    let (ramdisks, fleet_id) = if all {
+        let ramdisks = ramdisk::list()?
+            .into_iter()
+            .filter(|found| found.owned)
+            .collect::<Vec<_>>();
+
+        (ramdisks, None)
+    } else {
+        let config = config.context("no fleet.toml found, run `fleet init` first")?;
+        let ramdisk = ramdisk_dir(&config.fleet_id);
+
+        let ramdisks = ramdisk::list()?
+            .into_iter()
+            .filter(|found| found.path == ramdisk)
+            .collect();
+
+        (ramdisks, Some(config.fleet_id))
+    };
+
+    let mut cleaned = false;
+
+    for found in ramdisks {
+        if let Err(err) = std::fs::remove_dir_all(&found.path) {
+            eprintln!(
+                "{}: failed to remove {}: {err}",
+                "warning".bright_yellow(),
+                found.path.display()
+            );
+
+            continue;
+        }
+
+        cleaned = true;
+
+        if let Some(fleet_id) = found.path.file_name().and_then(|name| name.to_str()) {
+            remove_snapshot(fleet_id)?;
+        }
+
+        println!(
+            "💽 Removed {} ({})",
+            found.path.display(),
+            format_size(found.size)
+        );
+    }
+
+    // The snapshot outlives the ramdisk, eg. after a reboot, and would be restored on the next build
+    if let Some(fleet_id) = fleet_id {
+        if remove_snapshot(&fleet_id)? {
+            println!("💾 Removed the snapshot of the ramdisk");
+            cleaned = true;
+        }
+    }
+
+    if !cleaned {
+        println!("Nothing to clean");
+    }
+
+    Ok(())
+}
+
+/// Prints every ramdisk, the most recently used first
+fn list(app: App) -> Result<()> {
+    let App { config, .. } = app;
+    let current = config.map(|config| ramdisk_dir(&config.fleet_id));
+
+    let ramdisks = ramdisk::list()?;
+
+    if ramdisks.is_empty() {
+        println!("No ramdisk found in {}", ramdisk::RAMDISK_ROOT);
+        return Ok(());
+    }
+
+    let mut table = comfy_table::Table::new();
+
+    table
+        .load_preset(UTF8_FULL)
+        .apply_modifier(UTF8_ROUND_CORNERS)
+        .set_header(vec!["Ramdisk", "Project", "Size", "Last used"]);
+
+    for found in ramdisks.iter().rev() {
+        let project = match &found.project {
+            Some(project) if found.is_orphaned() => format!("{} (removed)", project.display()),
+            Some(project) => project.display().to_string(),
+            None => String::from("unknown"),
+        };
+
+        let name = Cell::new(found.path.display());
+
+        table.add_row(vec![
+            if current.as_ref() == Some(&found.path) {
+                name.fg(Color::Green)
+            } else {
+                name
+            },
+            Cell::new(project).fg(Color::Blue),
+            Cell::new(format_size(found.size)).fg(Color::Cyan),
+            Cell::new(format_age(found.last_used)),
+        ]);
+    }
+
+    println!("{table}");
+
+    let total: u64 = ramdisks.iter().map(|found| found.size).sum();
+
+    println!("Total: {}", format_size(total).bright_yellow());
+
+    Ok(())
+}
+
+/// Saves a snapshot of the ramdisk of the project, even when `rd_snapshot` is not set
+fn sync(app: App) -> Result<()> {
+    let App {
+        config, root_dir, ..
+    } = app;
+
+    let config = config.context("no fleet.toml found, run `fleet init` first")?;
+
+    let Some(ramdisk) = ManagedManifest::load(&root_dir)?.ramdisk else {
+        bail!("the project does not build on a ramdisk, run `fleet ramdisk enable`");
+    };
+
+    let size = snapshot(&ramdisk, &config.fleet_id)?;
+
+    println!("💾 Saved a snapshot of the ramdisk ({})", format_size(size));
+
+    Ok(())
+}
+
+pub fn run(mut app: App, action: RamdiskAction) -> Result<()> {
+    app.load_config()?;
+
+    match action {
+        RamdiskAction::Status => status(app),
+        RamdiskAction::Enable => toggle(app, true),
+        RamdiskAction::Disable => toggle(app, false),
+        RamdiskAction::Clean { all } => clean(app, all),
+        RamdiskAction::List => list(app),
+        RamdiskAction::Sync => sync(app),
+    }
+}
@@ -16,18 +16,42 @@
  */
 
 use crate::cli::app::App;
-use crate::core::config::enable::enable_fleet;
+use crate::core::commands::build::cargo_build;
+use crate::core::config::enable::{enable_fleet, requested_targets};
+use crate::utils::process::{exit_with, status_forwarding_signals};
 use anyhow::Result;
 use clap::Values;
 
-pub fn run(app: App, args: Option<Values>) -> Result<()> {
-    enable_fleet(app);
+/// Builds the project, then runs it with `cargo run`
+///
+/// The build shows the same progress as `fleet build`, the program is run by a quiet `cargo run`
+/// so that it keeps the terminal for its own input and output. Fleet exits with the status of
+/// the build when it fails, or else with the status of the program.
+pub fn run(mut app: App, args: Option<Values>, plain: bool) -> Result<()> {
+    let args: Vec<&str> = args.unwrap_or_default().collect();
+    let config = app.config_or_create()?;
 
-    let args = args.unwrap_or_default();
-    std::process::Command::new("cargo")
-        .arg("run")
-        .args(args)
-        .status()?;
+    // Arguments after `--` are passed to the program
+    let build_args = args.split(|arg| *arg == "--").next().unwrap_or_default();
+
+    enable_fleet(app, &requested_targets(build_args));
+
+    let status = cargo_build(build_args, plain, &config)?;
+
+    if !status.success() {
+        exit_with(status);
+    }
+
+    let status = status_forwarding_signals(
+        std::process::Command::new("cargo")
+            .arg("run")
+            .arg("--quiet")
+            .args(args),
+    )?;
+
+    if !status.success() {
+        exit_with(status);
+    }
 
     Ok(())
 }
@@ -15,124 +15,673 @@
  *    limitations under the License.
  */
 
-use std::process::exit;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::exit,
+};
 
+use crate::core::config::managed::{
+    remove_elements, restore_key, value_literal, ManagedKey, ManagedManifest,
+};
+use crate::core::config::{Linker, Preset};
 use ansi_term::Colour::Red;
 use serde::{Deserialize, Serialize};
+use toml_edit::{Array, Document, InlineTable, Item, Table};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConfigToml {
     pub build: Build,
     #[serde(rename = "target")]
-    pub target: Target,
+    pub target: BTreeMap<String, TargetValues>,
 
-    pub profile: Profile,
+    pub profile: BTreeMap<String, ProfileValues>,
 }
 
+/// Value of `opt-level`, either a number or `"s"`/`"z"`
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OptLevel {
+    Level(u8),
+    Size(String),
+}
+
+/// Values of a `[profile.<name>]` table, unset values are left to cargo
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct ProfileValues {
-    #[serde(rename = "opt-level")]
-    pub opt_level: u8,
-    pub debug: u8,
-    pub incremental: bool,
-    #[serde(rename = "codegen-units")]
-    pub codegen_units: u16,
+    pub opt_level: Option<OptLevel>,
+    pub debug: Option<u8>,
+    pub split_debuginfo: Option<String>,
+    pub codegen_units: Option<u16>,
+    pub lto: Option<String>,
+    pub incremental: Option<bool>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Profile {
-    pub dev: ProfileValues,
-    pub release: ProfileValues,
+impl ProfileValues {
+    /// Returns the values of a preset, `None` for [`Preset::None`]
+    #[must_use]
+    pub fn for_preset(preset: Preset) -> Option<Self> {
+        let values = match preset {
+            // The optimization level and debug info of the profile are kept, only knobs that do
+            // not change the behavior of the binary are set
+            Preset::FastDev => ProfileValues {
+                opt_level: None,
+                debug: None,
+                // Only the packed format is supported on Windows
+                split_debuginfo: (!cfg!(windows)).then(|| String::from("unpacked")),
+                codegen_units: Some(512),
+                lto: None,
+                incremental: Some(true),
+            },
+            Preset::Ci => ProfileValues {
+                opt_level: Some(OptLevel::Level(0)),
+                debug: Some(0),
+                split_debuginfo: None,
+                codegen_units: Some(256),
+                lto: None,
+                incremental: Some(false),
+            },
+            Preset::MaxPerf => ProfileValues {
+                opt_level: Some(OptLevel::Level(3)),
+                debug: Some(0),
+                split_debuginfo: None,
+                codegen_units: Some(1),
+                lto: Some(String::from("fat")),
+                incremental: Some(false),
+            },
+            Preset::SmallBinary => ProfileValues {
+                opt_level: Some(OptLevel::Size(String::from("z"))),
+                debug: Some(0),
+                split_debuginfo: None,
+                codegen_units: Some(1),
+                lto: Some(String::from("fat")),
+                incremental: Some(false),
+            },
+            Preset::None => return None,
+        };
+
+        Some(values)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Build {
     #[serde(rename = "rustc-wrapper")]
     pub rustc_wrapper: Option<String>,
+    /// The ramdisk directory, when enabled
+    #[serde(rename = "target-dir")]
+    pub target_dir: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TargetValues {
+    /// Left out when empty, an empty array would still override `build.rustflags`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub rustflags: Vec<String>,
     pub linker: Option<String>,
 }
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Target {
-    #[serde(rename = "x86_64-unknown-linux-gnu")]
-    pub linux: TargetValues,
-    #[serde(rename = "x86_64-pc-windows-msvc")]
-    pub windows: TargetValues,
-    #[serde(rename = "x86_64-apple-darwin")]
-    pub mac: TargetValues,
+
+/// Strategy used to speed up linking for a target triple
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkerStrategy {
+    /// `clang` is used as the linker driver with `-fuse-ld=lld`, `mold` is used instead of `lld` when available
+    ClangLld,
+    /// `rust-lld` is used as the linker
+    RustLld,
+    /// The system linker is kept, `zld` is used when available or `lld` when selected
+    Zld,
+}
+
+/// Linker strategies, matched in order against the operating system and environment of a target triple
+const LINKER_STRATEGIES: &[(&str, LinkerStrategy)] = &[
+    ("-linux-gnu", LinkerStrategy::ClangLld),
+    ("-linux-musl", LinkerStrategy::ClangLld),
+    ("-windows-msvc", LinkerStrategy::RustLld),
+    ("-apple-darwin", LinkerStrategy::Zld),
+];
+
+impl LinkerStrategy {
+    /// Finds the linker strategy for a target triple, `None` if fleet does not know how to configure it
+    #[must_use]
+    pub fn for_triple(triple: &str) -> Option<Self> {
+        LINKER_STRATEGIES
+            .iter()
+            .find(|(suffix, _)| triple.contains(suffix))
+            .map(|(_, strategy)| *strategy)
+    }
+}
+
+/// Paths to the tools used to build the cargo config
+#[derive(Debug, Default, Clone)]
+pub struct Tools {
+    /// The linker selected with `fleet configure`, `None` picks the fastest available one
+    pub linker: Option<Linker>,
+    pub sccache: Option<PathBuf>,
+    pub clang: Option<PathBuf>,
+    pub lld: Option<PathBuf>,
+    pub zld: Option<PathBuf>,
+    pub mold: Option<PathBuf>,
+}
+
+impl From<&crate::core::config::Build> for Tools {
+    fn from(build: &crate::core::config::Build) -> Self {
+        Self {
+            linker: build.linker,
+            sccache: build.sccache.clone(),
+            clang: build.clang.clone(),
+            lld: build.lld.clone(),
+            zld: build.zld.clone(),
+            mold: build.mold.clone(),
+        }
+    }
+}
+
+impl Tools {
+    /// Whether `linker` was found, the system linker is always available
+    #[must_use]
+    pub fn has(&self, linker: Linker) -> bool {
+        match linker {
+            Linker::Lld => self.lld.is_some(),
+            Linker::Mold => self.mold.is_some(),
+            Linker::Zld => self.zld.is_some(),
+            Linker::System => true,
+        }
+    }
+}
+
+/// Unwraps a item of Option<PathBuf> and returns the path as a String in Option<String>
+fn string_path(path: Option<&PathBuf>) -> Option<String> {
+    path.map(|path| path.to_string_lossy().to_string())
+}
+
+/// Returns the triple of the host, as reported by `rustc -vV`
+#[must_use]
+pub fn host_triple() -> Option<String> {
+    rustc_version::version_meta().ok().map(|meta| meta.host)
+}
+
+/// A flag passed to rustc in `rustflags`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RustFlag {
+    /// A codegen option, rendered as `-C<name>=<value>`
+    Codegen { name: &'static str, value: String },
+    /// An unstable option, rendered as `-Z<name>=<value>`, only accepted by nightly toolchains
+    Unstable { name: &'static str, value: String },
+}
+
+impl RustFlag {
+    /// `-Clink-arg=<arg>`, passes an argument down to the linker
+    #[must_use]
+    pub fn link_arg(arg: impl Into<String>) -> Self {
+        RustFlag::Codegen {
+            name: "link-arg",
+            value: arg.into(),
+        }
+    }
+
+    /// `-Csplit-debuginfo=<kind>`
+    #[must_use]
+    pub fn split_debuginfo(kind: &str) -> Self {
+        RustFlag::Codegen {
+            name: "split-debuginfo",
+            value: kind.to_string(),
+        }
+    }
+
+    /// `-Zshare-generics=y`
+    #[must_use]
+    pub fn share_generics() -> Self {
+        RustFlag::Unstable {
+            name: "share-generics",
+            value: String::from("y"),
+        }
+    }
+
+    /// Whether the flag is only accepted by nightly toolchains
+    #[must_use]
+    pub fn is_unstable(&self) -> bool {
+        matches!(self, RustFlag::Unstable { .. })
+    }
+}
+
+impl std::fmt::Display for RustFlag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RustFlag::Codegen { name, value } => write!(f, "-C{name}={value}"),
+            RustFlag::Unstable { name, value } => write!(f, "-Z{name}={value}"),
+        }
+    }
+}
+
+/// Builds the `rustflags` of a target
+#[derive(Debug, Default, Clone)]
+pub struct RustFlags {
+    flags: Vec<RustFlag>,
+}
+
+impl RustFlags {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, flag: RustFlag) -> &mut Self {
+        self.flags.push(flag);
+        self
+    }
+
+    /// Renders every flag, unstable flags are left out unless `nightly` is set
+    #[must_use]
+    pub fn render(&self, nightly: bool) -> Vec<String> {
+        self.flags
+            .iter()
+            .filter(|flag| nightly || !flag.is_unstable())
+            .map(ToString::to_string)
+            .collect()
+    }
+}
+
+/// Generates the `-fuse-ld` flag for a linker driven by `clang` or the system `cc`
+fn fuse_ld(linker: Linker, tools: &Tools) -> Option<RustFlag> {
+    match (linker, string_path(tools.mold.as_ref()), &tools.clang) {
+        // clang accepts the full path of the linker with `--ld-path`
+        (Linker::Mold, Some(mold), Some(_)) => {
+            Some(RustFlag::link_arg(format!("--ld-path={mold}")))
+        }
+        (Linker::Mold, _, _) => Some(RustFlag::link_arg("-fuse-ld=mold")),
+        (Linker::Lld, _, _) => Some(RustFlag::link_arg("-fuse-ld=lld")),
+        (Linker::Zld | Linker::System, _, _) => None,
+    }
+}
+
+/// Generates the `[target.<triple>]` section for a target triple
+///
+/// The linker selected in `tools` is used when it is supported by the target, otherwise the
+/// fastest available linker is picked. The system linker is kept when the linker was not found.
+///
+/// `host` is used to pass the triple down to `clang` when cross compiling, `-Z` flags are only
+/// generated when `nightly` is set as they are rejected by stable toolchains.
+#[must_use]
+pub fn target_values(
+    triple: &str,
+    host: Option<&str>,
+    tools: &Tools,
+    nightly: bool,
+) -> Option<TargetValues> {
+    let mut rustflags = RustFlags::new();
+
+    let linker = match LinkerStrategy::for_triple(triple)? {
+        LinkerStrategy::ClangLld => {
+            let linker = match tools.linker {
+                Some(linker @ (Linker::Lld | Linker::Mold | Linker::System)) => linker,
+                Some(Linker::Zld) | None if tools.mold.is_some() => Linker::Mold,
+                Some(Linker::Zld) | None => Linker::Lld,
+            };
+
+            // A linker that was not found would make every link fail
+            let linker = if tools.has(linker) {
+                linker
+            } else {
+                Linker::System
+            };
+
+            if let Some(flag) = fuse_ld(linker, tools) {
+                rustflags.push(flag);
+            }
+
+            rustflags.push(RustFlag::share_generics());
+
+            let clang = if linker == Linker::System {
+                None
+            } else {
+                string_path(tools.clang.as_ref())
+            };
+
+            if clang.is_some() && host != Some(triple) {
+                rustflags.push(RustFlag::link_arg(format!("--target={triple}")));
+            }
+
+            clang
+        }
+        LinkerStrategy::RustLld => {
+            rustflags.push(RustFlag::share_generics());
+
+            if tools.linker == Some(Linker::System) {
+                None
+            } else {
+                string_path(tools.lld.as_ref())
+            }
+        }
+        LinkerStrategy::Zld => {
+            rustflags
+                .push(RustFlag::share_generics())
+                .push(RustFlag::split_debuginfo("unpacked"));
+
+            match tools.linker {
+                Some(Linker::Lld) if tools.has(Linker::Lld) => {
+                    if let Some(flag) = fuse_ld(Linker::Lld, tools) {
+                        rustflags.push(flag);
+                    }
+                }
+                Some(Linker::Lld | Linker::System) => {}
+                Some(Linker::Zld | Linker::Mold) | None => {
+                    if let Some(zld) = string_path(tools.zld.as_ref()) {
+                        rustflags.push(RustFlag::link_arg(format!("-fuse-ld={zld}")));
+                    }
+                }
+            }
+
+            None
+        }
+    };
+
+    Some(TargetValues {
+        rustflags: rustflags.render(nightly),
+        linker,
+    })
+}
+
+/// Converts a `toml` value into its `toml_edit` equivalent
+fn to_edit_value(value: &toml::Value) -> toml_edit::Value {
+    match value {
+        toml::Value::String(string) => string.as_str().into(),
+        toml::Value::Integer(integer) => (*integer).into(),
+        toml::Value::Float(float) => (*float).into(),
+        toml::Value::Boolean(boolean) => (*boolean).into(),
+        toml::Value::Datetime(datetime) => datetime
+            .to_string()
+            .parse::<toml_edit::Datetime>()
+            .expect("Invalid datetime")
+            .into(),
+        toml::Value::Array(array) => array
+            .iter()
+            .map(to_edit_value)
+            .collect::<toml_edit::Array>()
+            .into(),
+        toml::Value::Table(table) => table
+            .iter()
+            .map(|(key, value)| (key, to_edit_value(value)))
+            .collect::<InlineTable>()
+            .into(),
+    }
+}
+
+/// Appends the `generated` elements that are missing from a user's array
+///
+/// The elements fleet added on a previous run are removed first, so that flags fleet no longer
+/// generates do not pile up. Returns the literals of the elements that were appended.
+fn merge_array(array: &mut Array, generated: &Array, managed: Option<&ManagedKey>) -> Vec<String> {
+    match managed {
+        Some(ManagedKey {
+            added: Some(added), ..
+        }) => remove_elements(array, added),
+        // Older manifests replaced the whole array, the user's elements are in its previous value
+        Some(ManagedKey { previous, .. }) => {
+            let previous = previous
+                .as_deref()
+                .and_then(|previous| previous.parse::<toml_edit::Value>().ok());
+
+            array.clear();
+
+            if let Some(toml_edit::Value::Array(previous)) = previous {
+                for value in &previous {
+                    array.push(value.clone());
+                }
+            }
+        }
+        None => {}
+    }
+
+    let existing: Vec<String> = array.iter().map(value_literal).collect();
+    let mut added = Vec::new();
+
+    for value in generated {
+        let literal = value_literal(value);
+
+        if !existing.contains(&literal) && !added.contains(&literal) {
+            array.push(value.clone());
+            added.push(literal);
+        }
+    }
+
+    added
+}
+
+/// Merges the keys owned by fleet into an existing table of the cargo config
+///
+/// Tables are merged recursively, values are replaced in place so that the comments and ordering
+/// around them are kept. Fleet's elements are appended to existing arrays. Anything that fleet
+/// does not generate is left untouched.
+///
+/// Every key that is written is recorded in `written` along with the value it replaced, `managed`
+/// holds the keys recorded by the previous run.
+fn merge_table(
+    item: &mut Item,
+    values: &toml::value::Table,
+    path: &mut Vec<String>,
+    managed: &[ManagedKey],
+    written: &mut Vec<ManagedKey>,
+) {
+    let inline = item.is_inline_table();
+
+    let Some(table) = item.as_table_like_mut() else {
+        return;
+    };
+
+    for (key, value) in values {
+        match value {
+            toml::Value::Table(values) => {
+                if !table.get(key).is_some_and(Item::is_table_like) {
+                    let child = if inline {
+                        Item::Value(InlineTable::new().into())
+                    } else {
+                        let mut child = Table::new();
+                        child.set_implicit(true);
+                        child.decor_mut().set_prefix("\n# Managed by Fleet\n");
+                        Item::Table(child)
+                    };
+
+                    table.insert(key, child);
+                }
+
+                if let Some(child) = table.get_mut(key) {
+                    path.push(key.clone());
+                    merge_table(child, values, path, managed, written);
+                    path.pop();
+                }
+            }
+            value => {
+                let mut key_path = path.clone();
+                key_path.push(key.clone());
+
+                let mut value = to_edit_value(value);
+                let mut previous = None;
+                let mut added = None;
+
+                if let Some(existing) = table.get_mut(key).and_then(Item::as_value_mut) {
+                    previous = Some(value_literal(existing));
+
+                    if let (Some(array), Some(generated)) =
+                        (existing.as_array_mut(), value.as_array())
+                    {
+                        let managed = managed.iter().find(|key| key.path == key_path);
+
+                        added = Some(merge_array(array, generated, managed));
+                    } else {
+                        *value.decor_mut() = existing.decor().clone();
+                        *existing = value;
+                    }
+                } else {
+                    if let Some(array) = value.as_array() {
+                        added = Some(array.iter().map(value_literal).collect());
+                    }
+
+                    table.insert(key, Item::Value(value));
+                }
+
+                written.push(ManagedKey {
+                    path: key_path,
+                    previous,
+                    added,
+                });
+            }
+        }
+    }
+}
+
+/// Generates the cargo config fleet writes for the host and every triple in `targets`
+///
+/// Nightly-only flags are left out unless `nightly` is set. A `[profile.<name>]` table is
+/// generated for every profile in `presets`, unless its preset is [`Preset::None`].
+///
+/// `build.target-dir` is set to `ramdisk` when the project builds on a ramdisk.
+#[must_use]
+pub fn generate_config(
+    tools: &Tools,
+    targets: &[String],
+    presets: &BTreeMap<String, Preset>,
+    ramdisk: Option<&Path>,
+    nightly: bool,
+) -> ConfigToml {
+    let host = host_triple();
+
+    let mut triples: Vec<&str> = host.iter().map(String::as_str).collect();
+
+    for target in targets {
+        if !triples.contains(&target.as_str()) {
+            triples.push(target);
+        }
+    }
+
+    let target = triples
+        .into_iter()
+        .filter_map(|triple| {
+            target_values(triple, host.as_deref(), tools, nightly)
+                .map(|values| (triple.to_string(), values))
+        })
+        .collect();
+
+    ConfigToml {
+        build: Build {
+            rustc_wrapper: string_path(tools.sccache.as_ref()),
+            target_dir: ramdisk.map(|ramdisk| ramdisk.to_string_lossy().to_string()),
+        },
+        target,
+        profile: presets
+            .iter()
+            .filter_map(|(name, preset)| {
+                ProfileValues::for_preset(*preset).map(|values| (name.clone(), values))
+            })
+            .collect(),
+    }
+}
+
+/// Flattens a TOML table into the dotted paths of its values
+fn flatten(prefix: &str, table: &toml::value::Table, values: &mut Vec<(String, toml::Value)>) {
+    for (key, value) in table {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+
+        match value {
+            toml::Value::Table(table) => flatten(&path, table, values),
+            value => values.push((path, value.clone())),
+        }
+    }
 }
 
+/// Compares an existing cargo config with the config fleet would generate
 ///
-/// Creates and writes the config into `./.cargo/config.toml`
+/// Returns the dotted paths of the keys that are missing or hold a different value, an array only
+/// has to contain the elements fleet generates.
+#[must_use]
+pub fn outdated_keys(contents: &str, config: &ConfigToml) -> Vec<String> {
+    let existing = toml::from_str::<toml::Value>(contents)
+        .unwrap_or_else(|_| toml::Value::Table(toml::value::Table::new()));
+
+    let mut expected = Vec::new();
+
+    if let Ok(toml::Value::Table(table)) = toml::Value::try_from(config) {
+        flatten("", &table, &mut expected);
+    }
+
+    let mut found = Vec::new();
+
+    if let toml::Value::Table(table) = existing {
+        flatten("", &table, &mut found);
+    }
+
+    let is_merged = |(path, value): &(String, toml::Value)| {
+        found.iter().any(|(found_path, found_value)| {
+            found_path == path
+                && match (value, found_value) {
+                    (toml::Value::Array(expected), toml::Value::Array(found)) => {
+                        expected.iter().all(|value| found.contains(value))
+                    }
+                    (value, found) => value == found,
+                }
+        })
+    };
+
+    expected
+        .into_iter()
+        .filter(|expected| !is_merged(expected))
+        .map(|(path, _)| path)
+        .collect()
+}
+
+///
+/// Merges the config into `./.cargo/config.toml`
 ///
 /// The `./.cargo/config.toml` is used by `cargo` to choose the building/running of a crate with rustc.
 ///
+/// A `[target.<triple>]` section is generated for the host and for every triple in `targets`,
+/// nightly-only flags are left out unless `nightly` is set. Profiles are configured with their
+/// preset from `presets` and the target directory is moved to the ramdisk of the `manifest`.
+///
+/// The existing file is parsed and only the keys generated by fleet are updated, every other table, comment
+/// and key is preserved as is.
+///
+/// The written keys are recorded in the `manifest`, keys that fleet wrote previously but no longer
+/// generates are reverted to their original value.
 ///
 /// # Panics
-/// Can panic if cannot prettify config
+/// Can panic if cannot serialize config
 pub fn add_rustc_wrapper_and_target_configs(
     path: &str,
-    sccache_path: Option<String>,
-    clang_path: Option<String>,
-    lld_path: Option<String>,
-    zld_path: Option<String>,
+    manifest: &mut ManagedManifest,
+    tools: &Tools,
+    targets: &[String],
+    presets: &BTreeMap<String, Preset>,
+    nightly: bool,
 ) {
-    let mut config: ConfigToml = ConfigToml {
-        build: Build {
-            rustc_wrapper: sccache_path,
-        },
-        target: Target {
-            mac: TargetValues {
-                rustflags: vec![
-                    String::from("-C"),
-                    String::from("-Zshare-generics=y"),
-                    String::from("-Csplit-debuginfo=unpacked"),
-                ],
-                linker: None,
-            },
-            windows: TargetValues {
-                rustflags: vec![String::from("-Zshare-generics=y")],
-                linker: lld_path,
-            },
-            linux: TargetValues {
-                rustflags: vec![
-                    String::from("-Clink-arg=-fuse-ld=lld"),
-                    String::from("-Zshare-generics=y"),
-                ],
-                linker: clang_path,
-            },
-        },
-        profile: Profile {
-            release: ProfileValues {
-                opt_level: 3,
-                debug: 0,
-                incremental: false,
-                codegen_units: 256,
-            },
-            dev: ProfileValues {
-                codegen_units: 512,
-                debug: 2,
-                incremental: true,
-                opt_level: 0,
-            },
-        },
-    };
+    let config = generate_config(
+        tools,
+        targets,
+        presets,
+        manifest.ramdisk.as_deref(),
+        nightly,
+    );
 
-    if let Some(zld) = zld_path {
-        config
-            .target
-            .mac
-            .rustflags
-            .push(format!("link-arg=-fuse-ld={}", zld));
-    }
+    let contents = std::fs::read_to_string(path).unwrap_or_default();
 
-    let toml_string = toml::to_string_pretty(&config).expect("Cannot prettify config");
+    let mut document = contents.parse::<Document>().unwrap_or_else(|err| {
+        eprintln!(
+            "{}: failed to parse existing configuration at {}: {}",
+            Red.paint("error"),
+            path,
+            err
+        );
+
+        exit(1);
+    });
+
+    merge_config(&mut document, &config, manifest);
 
-    std::fs::write(path, toml_string).unwrap_or_else(|err| {
+    std::fs::write(path, document.to_string()).unwrap_or_else(|err| {
         eprintln!(
             "{}: failed to write configuration: {}",
             Red.paint("error"),
@@ -144,3 +693,324 @@ pub fn add_rustc_wrapper_and_target_configs(
 
     println!("📝 Generated Fleet Config");
 }
+
+/// Merges the generated `config` into a cargo config `document` and records the written keys in
+/// the `manifest`
+///
+/// # Panics
+/// Can panic if cannot serialize config
+fn merge_config(document: &mut Document, config: &ConfigToml, manifest: &mut ManagedManifest) {
+    let values = toml::Value::try_from(config).expect("Cannot serialize config");
+
+    let mut written = Vec::new();
+
+    if let toml::Value::Table(values) = values {
+        merge_table(
+            document.as_item_mut(),
+            &values,
+            &mut Vec::new(),
+            &manifest.keys,
+            &mut written,
+        );
+    }
+
+    for key in &manifest.keys {
+        if !written.iter().any(|written| written.path == key.path) {
+            restore_key(document, key);
+        }
+    }
+
+    // Keys that were already managed keep the value they had before fleet first touched them
+    for key in &mut written {
+        if let Some(managed) = manifest.key(&key.path) {
+            key.previous = managed.previous.clone();
+        }
+    }
+
+    manifest.keys = written;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIPLES: &[&str] = &[
+        "x86_64-unknown-linux-gnu",
+        "x86_64-unknown-linux-musl",
+        "x86_64-pc-windows-msvc",
+        "aarch64-apple-darwin",
+    ];
+
+    const LINKERS: &[Linker] = &[Linker::Lld, Linker::Mold, Linker::Zld, Linker::System];
+
+    fn tools(linker: Option<Linker>) -> Tools {
+        Tools {
+            linker,
+            sccache: Some(PathBuf::from("/usr/bin/sccache")),
+            clang: Some(PathBuf::from("/usr/bin/clang")),
+            lld: Some(PathBuf::from("/usr/bin/ld.lld")),
+            zld: Some(PathBuf::from("/usr/local/bin/zld")),
+            mold: Some(PathBuf::from("/usr/bin/mold")),
+        }
+    }
+
+    /// Checks that every flag is a complete `-C` or `-Z` option, `-Z` only on nightly
+    fn assert_well_formed(rustflags: &[String], nightly: bool) {
+        for flag in rustflags {
+            let option = flag
+                .strip_prefix("-C")
+                .or_else(|| flag.strip_prefix("-Z").filter(|_| nightly))
+                .unwrap_or_else(|| panic!("unexpected flag `{flag}`"));
+
+            let (name, value) = option
+                .split_once('=')
+                .unwrap_or_else(|| panic!("flag `{flag}` has no value"));
+
+            assert!(
+                !name.is_empty() && !value.is_empty(),
+                "incomplete flag `{flag}`"
+            );
+            assert!(!name.contains(' '), "flag `{flag}` holds several options");
+        }
+    }
+
+    #[test]
+    fn target_values_are_well_formed() {
+        for triple in TRIPLES {
+            for linker in LINKERS.iter().copied().map(Some).chain([None]) {
+                for nightly in [false, true] {
+                    let values = target_values(triple, Some(triple), &tools(linker), nightly)
+                        .unwrap_or_else(|| panic!("no values for {triple}"));
+
+                    assert_well_formed(&values.rustflags, nightly);
+                    assert_eq!(
+                        values.rustflags.iter().any(|flag| flag.starts_with("-Z")),
+                        nightly,
+                        "{triple} with {linker:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generate_config_is_well_formed() {
+        let targets: Vec<String> = TRIPLES.iter().map(ToString::to_string).collect();
+        let presets = BTreeMap::from([(String::from("dev"), Preset::FastDev)]);
+
+        for linker in LINKERS {
+            for nightly in [false, true] {
+                let config =
+                    generate_config(&tools(Some(*linker)), &targets, &presets, None, nightly);
+
+                for triple in TRIPLES {
+                    assert!(config.target.contains_key(*triple), "{triple} is missing");
+                }
+
+                for values in config.target.values() {
+                    assert_well_formed(&values.rustflags, nightly);
+                }
+
+                assert_eq!(
+                    config.build.rustc_wrapper.as_deref(),
+                    Some("/usr/bin/sccache")
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn selected_linker_is_used() {
+        let linux = "x86_64-unknown-linux-gnu";
+
+        let lld = target_values(linux, Some(linux), &tools(Some(Linker::Lld)), false).unwrap();
+        assert_eq!(lld.rustflags, ["-Clink-arg=-fuse-ld=lld"]);
+        assert_eq!(lld.linker.as_deref(), Some("/usr/bin/clang"));
+
+        let mold = target_values(linux, Some(linux), &tools(Some(Linker::Mold)), false).unwrap();
+        assert_eq!(mold.rustflags, ["-Clink-arg=--ld-path=/usr/bin/mold"]);
+
+        let system =
+            target_values(linux, Some(linux), &tools(Some(Linker::System)), false).unwrap();
+        assert!(system.rustflags.is_empty());
+        assert_eq!(system.linker, None);
+
+        let darwin = "aarch64-apple-darwin";
+        let zld = target_values(darwin, Some(darwin), &tools(Some(Linker::Zld)), false).unwrap();
+        assert!(zld
+            .rustflags
+            .contains(&String::from("-Clink-arg=-fuse-ld=/usr/local/bin/zld")));
+    }
+
+    #[test]
+    fn missing_linker_falls_back_to_system() {
+        let linux = "x86_64-unknown-linux-gnu";
+        let tools = Tools {
+            linker: Some(Linker::Lld),
+            ..Tools::default()
+        };
+
+        let values = target_values(linux, Some(linux), &tools, true).unwrap();
+
+        assert_eq!(values.rustflags, ["-Zshare-generics=y"]);
+        assert_eq!(values.linker, None);
+    }
+
+    #[test]
+    fn cross_compiling_passes_the_target_to_clang() {
+        let musl = "x86_64-unknown-linux-musl";
+        let values = target_values(
+            musl,
+            Some("x86_64-unknown-linux-gnu"),
+            &tools(Some(Linker::Lld)),
+            false,
+        )
+        .unwrap();
+
+        assert!(values
+            .rustflags
+            .contains(&format!("-Clink-arg=--target={musl}")));
+    }
+
+    /// Renders the `[target.<triple>]` table fleet writes into an empty cargo config
+    fn render(triple: &str, tools: &Tools, nightly: bool) -> String {
+        let config = ConfigToml {
+            build: Build {
+                rustc_wrapper: None,
+                target_dir: None,
+            },
+            target: BTreeMap::from([(
+                triple.to_string(),
+                target_values(triple, Some(triple), tools, nightly).unwrap(),
+            )]),
+            profile: BTreeMap::new(),
+        };
+
+        let mut document = Document::new();
+        merge_config(&mut document, &config, &mut ManagedManifest::default());
+
+        document.to_string()
+    }
+
+    #[test]
+    fn linux_clang_lld_snapshot() {
+        let linux = "x86_64-unknown-linux-gnu";
+        let tools = tools(Some(Linker::Lld));
+
+        assert_eq!(
+            render(linux, &tools, false),
+            r#"
+# Managed by Fleet
+[target.x86_64-unknown-linux-gnu]
+rustflags = ["-Clink-arg=-fuse-ld=lld"]
+linker = "/usr/bin/clang"
+"#
+        );
+        assert_eq!(
+            render(linux, &tools, true),
+            r#"
+# Managed by Fleet
+[target.x86_64-unknown-linux-gnu]
+rustflags = ["-Clink-arg=-fuse-ld=lld", "-Zshare-generics=y"]
+linker = "/usr/bin/clang"
+"#
+        );
+    }
+
+    #[test]
+    fn linux_mold_snapshot() {
+        let linux = "x86_64-unknown-linux-gnu";
+        let tools = tools(Some(Linker::Mold));
+
+        assert_eq!(
+            render(linux, &tools, false),
+            r#"
+# Managed by Fleet
+[target.x86_64-unknown-linux-gnu]
+rustflags = ["-Clink-arg=--ld-path=/usr/bin/mold"]
+linker = "/usr/bin/clang"
+"#
+        );
+        assert_eq!(
+            render(linux, &tools, true),
+            r#"
+# Managed by Fleet
+[target.x86_64-unknown-linux-gnu]
+rustflags = ["-Clink-arg=--ld-path=/usr/bin/mold", "-Zshare-generics=y"]
+linker = "/usr/bin/clang"
+"#
+        );
+    }
+
+    #[test]
+    fn darwin_zld_snapshot() {
+        let darwin = "aarch64-apple-darwin";
+        let tools = tools(Some(Linker::Zld));
+
+        assert_eq!(
+            render(darwin, &tools, false),
+            r#"
+# Managed by Fleet
+[target.aarch64-apple-darwin]
+rustflags = ["-Csplit-debuginfo=unpacked", "-Clink-arg=-fuse-ld=/usr/local/bin/zld"]
+"#
+        );
+        assert_eq!(
+            render(darwin, &tools, true),
+            r#"
+# Managed by Fleet
+[target.aarch64-apple-darwin]
+rustflags = ["-Zshare-generics=y", "-Csplit-debuginfo=unpacked", "-Clink-arg=-fuse-ld=/usr/local/bin/zld"]
+"#
+        );
+    }
+
+    #[test]
+    fn windows_rust_lld_snapshot() {
+        let windows = "x86_64-pc-windows-msvc";
+        let tools = tools(None);
+
+        assert_eq!(
+            render(windows, &tools, false),
+            r#"
+# Managed by Fleet
+[target.x86_64-pc-windows-msvc]
+linker = "/usr/bin/ld.lld"
+"#
+        );
+        assert_eq!(
+            render(windows, &tools, true),
+            r#"
+# Managed by Fleet
+[target.x86_64-pc-windows-msvc]
+rustflags = ["-Zshare-generics=y"]
+linker = "/usr/bin/ld.lld"
+"#
+        );
+    }
+
+    #[test]
+    fn system_fallback_snapshot() {
+        let linux = "x86_64-unknown-linux-gnu";
+        let tools = Tools {
+            linker: Some(Linker::Lld),
+            ..Tools::default()
+        };
+
+        // Nothing is left to write, the system linker is kept with the user's flags
+        assert_eq!(render(linux, &tools, false), "");
+        assert_eq!(
+            render(linux, &tools, true),
+            r#"
+# Managed by Fleet
+[target.x86_64-unknown-linux-gnu]
+rustflags = ["-Zshare-generics=y"]
+"#
+        );
+    }
+
+    #[test]
+    fn unknown_triple_has_no_values() {
+        assert!(target_values("wasm32-unknown-unknown", None, &tools(None), true).is_none());
+    }
+}
@@ -15,82 +15,106 @@
  *    limitations under the License.
  */
 
-use crate::core::config::cargo::add_rustc_wrapper_and_target_configs;
-use ansi_term::Colour::{Green, Red};
-use std::{
-    path::{self, PathBuf},
-    process::{exit, Command},
-};
-
-/// Unwraps a item of Option<PathBuf> and returns the path as a String in Option<String>
-fn string_path_unwrap(path: Option<PathBuf>) -> Option<String> {
-    path.map(|path| path.to_str().unwrap().to_string())
-}
+use crate::core::config::cargo::{add_rustc_wrapper_and_target_configs, Tools};
+use crate::core::config::managed::ManagedManifest;
+use crate::core::config::toolchain::{is_nightly, pin_toolchain};
+use crate::core::ramdisk;
+use crate::utils::disk::disk_kind;
+use ansi_term::Colour::{Green, Red, Yellow};
+use std::process::{exit, Command};
+
+/// Collects the target triples passed with `--target` to a cargo command
+#[must_use]
+pub fn requested_targets(args: &[&str]) -> Vec<String> {
+    let mut targets = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if let Some(target) = arg.strip_prefix("--target=") {
+            targets.push(target.to_string());
+        } else if *arg == "--target" {
+            if let Some(target) = args.next() {
+                targets.push((*target).to_string());
+            }
+        }
+    }
 
-#[cfg(unix)]
-#[allow(unused_imports)]
-use sysinfo::{DiskExt, DiskType, RefreshKind, System, SystemExt};
+    targets
+}
 
-/// If the `./.cargo/config.toml` doesn't exist, it is created.
+/// If the `fleet.toml` or the `.cargo/config.toml` of the project root don't exist, they are created.
+///
+/// The application config is written onto the `.cargo/config.toml`.
 ///
-/// The application config is written onto the `./.cargo/config.toml`.
+/// If a toolchain is set in `fleet.toml` it is pinned in `rust-toolchain.toml`, nightly-only flags
+/// are only generated when the active toolchain is nightly.
 ///
-/// Ramdisk improvements are applied if the disk is a HDD and the program is using WSL
+/// Target sections are generated for the host triple and every triple in `targets`.
+///
+/// The target directory is moved to a ramdisk when `rd_enabled` is set in `fleet.toml`, through
+/// `build.target-dir` so that the `target` directory of the project is never deleted. The project
+/// builds on disk when its ramdisk is over `rd_size_limit` or does not fit on the tmpfs.
+///
+/// Every change is recorded in the `fleet-managed.toml` manifest so that it can be undone by `fleet eject`.
 ///
 ///  # Panics
 /// Can panic if cannot get `dirs::home_dir`
-pub fn enable_fleet(app: crate::cli::app::App) {
-    let cargo_toml = path::Path::new("./Cargo.toml");
+pub fn enable_fleet(mut app: crate::cli::app::App, targets: &[String]) {
+    let config = app.config_or_create().unwrap_or_else(|err| {
+        eprintln!("{}: {:#}", Red.paint("error"), err);
+        exit(1);
+    });
+    let root_dir = app.root_dir;
+    let cargo_toml = root_dir.join("Cargo.toml");
 
     if !cargo_toml.exists() {
-        if let Err(cmd) = Command::new("cargo").arg("init").status() {
+        if let Err(cmd) = Command::new("cargo")
+            .arg("init")
+            .current_dir(&root_dir)
+            .status()
+        {
             eprintln!("{}: failed to run cargo init: {}", Red.paint("error"), cmd);
             exit(1);
         }
     }
 
-    let config = app.config;
-    let os = std::env::consts::OS;
+    let mut manifest = ManagedManifest::load(&root_dir).unwrap_or_else(|err| {
+        eprintln!("{}: {:#}", Red.paint("error"), err);
+        exit(1);
+    });
 
-    if os != "windows" {
-        // ramdisk improvements are only found if the disk is a HDD and the program is using WSL
-        #[cfg(target_os = "linux")]
-        {
-            let refresh_kind = RefreshKind::new();
-            let disks = refresh_kind.with_disks_list();
-            let system = System::new_with_specifics(disks);
-            let disk = system.disks().get(0).unwrap();
-
-            if disk.type_() == DiskType::HDD || wsl::is_wsl() {
-                let ramdisk_dir = path::Path::new("/dev/shm");
-                let fleet_dir = ramdisk_dir.join(&config.fleet_id);
-                let target_dir = std::env::current_dir().unwrap().join("target");
-
-                // check if target_dir is not a symlink, if yes delete it
-                if !target_dir.is_symlink() && target_dir.exists() {
-                    if let Err(err) = std::fs::remove_dir_all(target_dir.clone()) {
-                        eprintln!("{} {}", Red.paint("error: "), &err);
-                        exit(1)
-                    }
-                }
-
-                if !fleet_dir.exists() {
-                    if let Err(err) = std::fs::create_dir(fleet_dir.clone()) {
-                        eprintln!("{} {}", Red.paint("error: "), &err);
-                        exit(1)
-                    }
-                }
-
-                if !target_dir.exists() {
-                    println!("💽 Creating Ramdisk");
-                    std::os::unix::fs::symlink(fleet_dir, target_dir).unwrap();
-                }
+    if let Some(channel) = &config.toolchain {
+        if let Err(err) = pin_toolchain(&root_dir, channel, &mut manifest) {
+            eprintln!("{}: {:#}", Red.paint("error"), err);
+            exit(1);
+        }
+    }
+
+    if config.rd_enabled {
+        match ramdisk::prepare(&root_dir, &config, manifest.ramdisk.is_none()) {
+            Ok(ramdisk) => manifest.ramdisk = Some(ramdisk),
+            Err(err) => {
+                eprintln!("{}: {:#}, building on disk", Yellow.paint("warning"), err);
+
+                manifest.ramdisk = None;
             }
         }
+    } else {
+        manifest.ramdisk = None;
+
+        let disk = disk_kind(&root_dir);
+
+        // ramdisk improvements are only found on hard drives and network filesystems, eg. the
+        // Windows drives of WSL
+        if disk.is_slow() {
+            println!(
+                "💡 Run `fleet ramdisk enable` to build on a ramdisk, it is faster than this {disk}"
+            );
+        }
     }
 
     // https://doc.rust-lang.org/cargo/reference/config.html
-    let cargo_manifest_dir = std::env::current_dir().unwrap().join(".cargo");
+    let cargo_manifest_dir = root_dir.join(".cargo");
 
     std::fs::create_dir_all(&cargo_manifest_dir).unwrap();
 
@@ -106,19 +130,31 @@ pub fn enable_fleet(app: crate::cli::app::App) {
             );
             exit(1);
         });
+
+        manifest.created_cargo_config = true;
     }
 
+    let config_path = if config_toml.exists() {
+        config_toml
+    } else {
+        config_no_toml
+    };
+
     add_rustc_wrapper_and_target_configs(
-        if config_toml.exists() {
-            config_toml.to_str().unwrap()
-        } else {
-            config_no_toml.to_str().unwrap()
-        },
-        string_path_unwrap(config.build.sccache),
-        string_path_unwrap(config.build.clang),
-        string_path_unwrap(config.build.lld),
-        string_path_unwrap(config.build.zld),
+        config_path.to_str().unwrap(),
+        &mut manifest,
+        &Tools::from(&config.build),
+        targets,
+        &config.presets(),
+        is_nightly(),
     );
 
+    manifest.cargo_config = Some(config_path);
+
+    if let Err(err) = manifest.save(&root_dir) {
+        eprintln!("{}: {:#}", Red.paint("error"), err);
+        exit(1);
+    }
+
     println!("🚀 {}", Green.paint("Fleet is ready!"));
 }
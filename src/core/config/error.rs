@@ -0,0 +1,259 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::cmp::Reverse;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Error found while reading a fleet config file
+#[derive(Debug)]
+pub struct ConfigError {
+    /// Path of the invalid config file
+    pub path: PathBuf,
+    pub message: String,
+    /// Line and column of the error, both 0-based
+    pub position: Option<(usize, usize)>,
+    /// The line of the config file the error points to
+    pub source_line: Option<String>,
+    /// Number of characters underlined by the caret
+    pub span_len: usize,
+    /// A known key or value close to the invalid one
+    pub suggestion: Option<String>,
+}
+
+impl ConfigError {
+    /// Creates an error from a toml error raised while parsing `contents`
+    #[must_use]
+    pub fn from_toml(path: &Path, contents: &str, error: &toml::de::Error) -> Self {
+        let mut message = error.to_string();
+        let mut position = error.line_col();
+
+        // The position is rendered separately
+        if position.is_some() {
+            if let Some((rest, _)) = message.rsplit_once(" at line ") {
+                message = rest.to_string();
+            }
+        }
+
+        let unknown = unknown_name(&message);
+
+        let mut span_len = 1;
+
+        // toml points unknown keys and variants at the start of their table
+        if let Some((name, _)) = unknown {
+            let found = if message.starts_with("unknown variant") {
+                find_value(contents, name).map(|position| (position, name.chars().count() + 2))
+            } else {
+                find_key(contents, name).map(|position| (position, name.chars().count()))
+            };
+
+            if let Some((found, len)) = found {
+                position = Some(found);
+                span_len = len;
+            }
+        }
+
+        // Errors of values deserialized from a parsed table only name their key, and custom
+        // errors of a value are also pointed at the end of its table
+        if unknown.is_none() {
+            if let Some(key) = error_key(&message) {
+                let name = key.rsplit('.').next().unwrap_or(key);
+
+                if let Some(found) = find_key(contents, name) {
+                    position = Some(found);
+                    span_len = name.chars().count();
+                }
+            }
+        }
+
+        let suggestion = unknown.and_then(|(name, expected)| closest_match(name, &expected));
+
+        Self {
+            path: path.to_path_buf(),
+            source_line: position
+                .and_then(|(line, _)| contents.lines().nth(line).map(str::to_string)),
+            message,
+            position,
+            span_len,
+            suggestion,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid fleet configuration in {}: {}",
+            self.path.display(),
+            self.message
+        )?;
+
+        if let (Some((line, column)), Some(source_line)) = (self.position, &self.source_line) {
+            let number = (line + 1).to_string();
+            let gutter = " ".repeat(number.len());
+
+            write!(
+                f,
+                "\n{gutter}--> {}:{}:{}\n{gutter} |\n{number} | {source_line}\n{gutter} | {}{}",
+                self.path.display(),
+                line + 1,
+                column + 1,
+                " ".repeat(column),
+                "^".repeat(self.span_len.max(1)),
+            )?;
+        }
+
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n\nhelp: did you mean `{suggestion}`?")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Extracts the name and the expected names from an `unknown field` or `unknown variant` message
+fn unknown_name(message: &str) -> Option<(&str, Vec<&str>)> {
+    let rest = message
+        .strip_prefix("unknown field `")
+        .or_else(|| message.strip_prefix("unknown variant `"))?;
+
+    let (name, expected) = rest.split_once('`')?;
+
+    let expected = expected
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .collect::<Vec<&str>>();
+
+    Some((name, expected))
+}
+
+/// Extracts the dotted key from a message ending with "for key `key`"
+fn error_key(message: &str) -> Option<&str> {
+    let (_, key) = message.rsplit_once(" for key `")?;
+
+    key.strip_suffix('`')
+}
+
+/// Finds the position of a key defined in `contents`
+fn find_key(contents: &str, key: &str) -> Option<(usize, usize)> {
+    contents.lines().enumerate().find_map(|(number, line)| {
+        let column = line.len() - line.trim_start().len();
+        let rest = line[column..].strip_prefix(key)?;
+
+        rest.trim_start()
+            .starts_with(['=', '.'])
+            .then_some((number, column))
+    })
+}
+
+/// Finds the position of a string value in `contents`
+fn find_value(contents: &str, value: &str) -> Option<(usize, usize)> {
+    let quoted = [format!("\"{value}\""), format!("'{value}'")];
+
+    contents.lines().enumerate().find_map(|(number, line)| {
+        quoted
+            .iter()
+            .find_map(|quoted| line.find(quoted.as_str()))
+            .map(|column| (number, column))
+    })
+}
+
+/// Returns the name of `candidates` closest to `name`, if any is close enough
+///
+/// Ties go to the candidate sharing the longest prefix with `name`.
+pub(crate) fn closest_match(name: &str, candidates: &[&str]) -> Option<String> {
+    candidates
+        .iter()
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= 3 && *distance < candidate.len())
+        .min_by_key(|(distance, candidate)| (*distance, Reverse(common_prefix(name, candidate))))
+        .map(|(_, candidate)| (*candidate).to_string())
+}
+
+/// Number of leading characters `a` and `b` have in common
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(a, b)| a == b).count()
+}
+
+/// Damerau-Levenshtein distance between two strings, in its optimal string alignment variant
+///
+/// Swapping two adjacent characters counts as a single edit, eg. `mlod` is 1 away from `mold`.
+fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+
+    // `rows[i][j]` is the distance between the first `i` characters of `a` and `j` of `b`
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+
+            rows[i][j] = best;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest_match, distance};
+
+    #[test]
+    fn transpositions_are_a_single_edit() {
+        assert_eq!(distance("mlod", "mold"), 1);
+        assert_eq!(distance("mold", "mold"), 0);
+        assert_eq!(distance("", "lld"), 3);
+        assert_eq!(distance("sccahe", "sccache"), 1);
+    }
+
+    #[test]
+    fn closest_match_prefers_the_closest_candidate() {
+        let linkers = ["lld", "mold", "zld", "system"];
+
+        assert_eq!(closest_match("mlod", &linkers).as_deref(), Some("mold"));
+        assert_eq!(closest_match("zdl", &linkers).as_deref(), Some("zld"));
+        assert_eq!(closest_match("ramdisk", &linkers), None);
+    }
+
+    #[test]
+    fn ties_go_to_the_longest_common_prefix() {
+        assert_eq!(closest_match("ld", &["zld", "lld"]).as_deref(), Some("lld"));
+        assert_eq!(closest_match("ld", &["lld", "zld"]).as_deref(), Some("lld"));
+    }
+}
@@ -15,32 +15,58 @@
  *    limitations under the License.
  */
 
-use crate::core::config::find;
+use crate::core::config::{error::ConfigError, find};
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf, process::exit};
+use std::{fs, path::PathBuf};
+
+/// Every key of the global config file, as dotted paths
+pub const KEYS: &[&str] = &[
+    "build.sccache",
+    "build.lld",
+    "build.clang",
+    "build.zld",
+    "build.mold",
+];
 
 /// Represents the Build table of the global config file at `{home_dir}/.config/fleet/config.toml`
-#[derive(Deserialize, Debug, Serialize, Clone)]
+#[derive(Deserialize, Debug, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Build {
+    /// Path of the `sccache` binary, used as the rustc wrapper
     pub sccache: Option<PathBuf>,
+    /// Path of the `lld` binary
     pub lld: Option<PathBuf>,
+    /// Path of the `clang` binary, used as the linker driver on Linux
     pub clang: Option<PathBuf>,
+    /// Path of the `zld` binary
     pub zld: Option<PathBuf>,
+    /// Path of the `mold` binary
+    pub mold: Option<PathBuf>,
 }
-/// Represents the Build table of the global config file at `{home_dir}/.config/fleet/config.toml`
-#[derive(Deserialize, Debug, Serialize, Clone)]
+/// Represents the global config file at `{home_dir}/.config/fleet/config.toml`
+#[derive(Deserialize, Debug, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct FleetGlobalConfig {
     pub build: Build,
 }
 
 impl FleetGlobalConfig {
+    /// Path of the global config file
+    pub fn path() -> Result<PathBuf> {
+        let home_dir = dirs::home_dir().context("cannot find home directory")?;
+
+        Ok(home_dir.join(".config").join("fleet").join("config.toml"))
+    }
+
     /// If the global fleet config file is not found, it is created with the basic settings and the config is returned.
     ///
     /// If the file exists at `{home_dir}/.config/fleet`, it is read and parsed into a `FleetGlobalConfig` instance and returned.
     ///
     ///  # Panics
     /// can panic if home dir not found
-    pub fn run_config() -> Self {
+    pub fn run_config() -> Result<Self> {
         let config_dir = dirs::home_dir().unwrap().join(".config").join("fleet");
 
         if !config_dir.join(".config").join("fleet").exists() {
@@ -50,21 +76,31 @@ impl FleetGlobalConfig {
         let config_path = config_dir.join("config.toml");
 
         if config_path.exists() {
-            let config_file = fs::read_to_string(&config_path).unwrap();
-            if let Ok(config) = toml::from_str::<Self>(&config_file) {
-                return config;
+            let config_file = fs::read_to_string(&config_path)
+                .with_context(|| format!("failed to read {}", config_path.display()))?;
+
+            let mut config = toml::from_str::<Self>(&config_file)
+                .map_err(|err| ConfigError::from_toml(&config_path, &config_file, &err))?;
+
+            // Older versions wrote the `lld` of Windows on every platform
+            if !cfg!(windows) && config.build.lld == Some(PathBuf::from("rust-lld.exe")) {
+                config.build.lld = find("ld.lld");
             }
 
-            println!("Invalid fleet global configuration");
-            exit(1)
+            return Ok(config);
         }
 
         let config = FleetGlobalConfig {
             build: Build {
                 sccache: find("sccache"),
-                lld: Some(PathBuf::from("rust-lld.exe")),
+                lld: if cfg!(windows) {
+                    Some(PathBuf::from("rust-lld.exe"))
+                } else {
+                    find("ld.lld")
+                },
                 clang: find("clang"),
                 zld: find("zld"),
+                mold: find("mold"),
             },
         };
 
@@ -73,6 +109,6 @@ impl FleetGlobalConfig {
 
         println!("📝 Generated Fleet Global Config");
 
-        config
+        Ok(config)
     }
 }
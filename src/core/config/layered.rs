@@ -0,0 +1,365 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use crate::core::config::error::{closest_match, ConfigError};
+use crate::core::config::global::FleetGlobalConfig;
+use crate::core::config::migrate::migrate;
+use crate::core::config::{FleetConfig, CONFIG_FILE, KEYS};
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+
+/// Where a config value comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// The global config file at `{home_dir}/.config/fleet/config.toml`
+    Global(PathBuf),
+    /// The `fleet.toml` of the project root
+    Workspace(PathBuf),
+    /// The `fleet.toml` of the workspace member in the current directory
+    Package(PathBuf),
+    /// A `FLEET_*` environment variable
+    Env(String),
+    /// A `--config key=value` flag
+    Cli,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Global(path) => write!(f, "global:{}", path.display()),
+            Origin::Workspace(path) => write!(f, "workspace:{}", path.display()),
+            Origin::Package(path) => write!(f, "package:{}", path.display()),
+            Origin::Env(name) => write!(f, "env:{name}"),
+            Origin::Cli => write!(f, "cli:--config"),
+        }
+    }
+}
+
+/// The values of a config layer, keyed by their dotted path, eg. `build.sccache`
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub origin: Origin,
+    pub values: BTreeMap<String, Value>,
+}
+
+/// Every config layer of a project, from the lowest to the highest priority
+///
+/// Layers are resolved in order: global config, `fleet.toml` of the workspace root, `fleet.toml`
+/// of the current workspace member, `FLEET_*` environment variables and `--config` flags.
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    pub layers: Vec<Layer>,
+}
+
+impl LayeredConfig {
+    /// Reads every config layer of the project at `root_dir`
+    pub fn load(root_dir: &Path, current_dir: &Path, overrides: &[String]) -> Result<Self> {
+        let mut layers = Vec::new();
+
+        let global_path = FleetGlobalConfig::path()?;
+        let global = Value::try_from(FleetGlobalConfig::run_config()?)?;
+
+        layers.push(Layer {
+            origin: Origin::Global(global_path),
+            values: flatten(&global),
+        });
+
+        let workspace_path = root_dir.join(CONFIG_FILE);
+
+        if workspace_path.exists() {
+            migrate(&workspace_path)?;
+            layers.push(read_file(
+                &workspace_path,
+                Origin::Workspace(workspace_path.clone()),
+            )?);
+        }
+
+        if let Some(package_dir) = package_dir(root_dir, current_dir) {
+            let package_path = package_dir.join(CONFIG_FILE);
+
+            if package_path.exists() {
+                migrate(&package_path)?;
+                layers.push(read_file(
+                    &package_path,
+                    Origin::Package(package_path.clone()),
+                )?);
+            }
+        }
+
+        // Profiles accept any name, their variables are found by prefix
+        let profiles = std::env::vars_os().filter_map(|(name, _)| {
+            let name = name.to_str()?.strip_prefix(PROFILE_ENV_PREFIX)?;
+
+            Some(format!("profile.{}", name.to_lowercase()))
+        });
+
+        let keys = KEYS
+            .iter()
+            .map(ToString::to_string)
+            .chain(profiles)
+            .filter(|key| KEYS.contains(&key.as_str()) || is_profile_key(key))
+            .collect::<BTreeSet<String>>();
+
+        let env = keys
+            .into_iter()
+            .filter_map(|key| {
+                let value = std::env::var(env_name(&key)).ok()?;
+
+                Some(Layer {
+                    origin: Origin::Env(env_name(&key)),
+                    values: BTreeMap::from([(key, parse_value(&value))]),
+                })
+            })
+            .collect::<Vec<Layer>>();
+
+        layers.extend(env);
+
+        for item in overrides {
+            let Some((key, value)) = item.split_once('=') else {
+                bail!("invalid `--config {item}`, expected `key=value`");
+            };
+
+            let key = key.trim();
+
+            check_key(key, KEYS)?;
+
+            layers.push(Layer {
+                origin: Origin::Cli,
+                values: BTreeMap::from([(key.to_string(), parse_value(value.trim()))]),
+            });
+        }
+
+        for layer in &layers {
+            if matches!(layer.origin, Origin::Env(_) | Origin::Cli) {
+                validate(&layer.values)
+                    .with_context(|| format!("invalid value from {}", layer.origin))?;
+            }
+        }
+
+        Ok(Self { layers })
+    }
+
+    /// Returns the effective value of `key` and the layer it comes from
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<(&Value, &Origin)> {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|layer| Some((layer.values.get(key)?, &layer.origin)))
+    }
+
+    /// Returns the effective value of every key that is set, with the layer it comes from
+    #[must_use]
+    pub fn values(&self) -> Vec<(String, &Value, &Origin)> {
+        let profiles = self
+            .layers
+            .iter()
+            .flat_map(|layer| layer.values.keys())
+            .filter(|key| !KEYS.contains(&key.as_str()) && is_profile_key(key))
+            .cloned()
+            .collect::<BTreeSet<String>>();
+
+        KEYS.iter()
+            .map(ToString::to_string)
+            .chain(profiles)
+            .filter_map(|key| {
+                let (value, origin) = self.get(&key)?;
+
+                Some((key, value, origin))
+            })
+            .collect()
+    }
+
+    /// Resolves the effective `FleetConfig`
+    ///
+    /// Returns `None` if the project root has no `fleet.toml`.
+    pub fn config(&self) -> Result<Option<FleetConfig>> {
+        let initialized = self
+            .layers
+            .iter()
+            .any(|layer| matches!(layer.origin, Origin::Workspace(_)));
+
+        if !initialized {
+            return Ok(None);
+        }
+
+        let mut values = defaults();
+
+        for layer in &self.layers {
+            values.extend(layer.values.clone());
+        }
+
+        let config = unflatten(&values)
+            .try_into::<FleetConfig>()
+            .context("invalid fleet configuration")?;
+
+        Ok(Some(config))
+    }
+}
+
+/// Prefix of the environment variables overriding the preset of a profile, eg. `FLEET_PROFILE_BENCH`
+const PROFILE_ENV_PREFIX: &str = "FLEET_PROFILE_";
+
+/// Name of the environment variable overriding `key`, eg. `FLEET_BUILD_SCCACHE`
+#[must_use]
+pub fn env_name(key: &str) -> String {
+    format!("FLEET_{}", key.replace('.', "_").to_uppercase())
+}
+
+/// Whether `key` is the preset of a cargo profile, eg. `profile.bench`
+///
+/// Profiles are not listed in [`KEYS`] beyond the built-in ones, any valid profile name is accepted.
+fn is_profile_key(key: &str) -> bool {
+    key.strip_prefix("profile.").is_some_and(|name| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_')
+    })
+}
+
+/// Checks that `key` is one of `keys`, or the preset of a profile when `keys` has profiles
+pub fn check_key(key: &str, keys: &[&str]) -> Result<()> {
+    let has_profiles = keys.iter().any(|known| known.starts_with("profile."));
+
+    if keys.contains(&key) || (has_profiles && is_profile_key(key)) {
+        return Ok(());
+    }
+
+    match closest_match(key, keys) {
+        Some(suggestion) => bail!("unknown config key `{key}`, did you mean `{suggestion}`?"),
+        None => bail!("unknown config key `{key}`"),
+    }
+}
+
+/// Parses a value given on the command line or in an environment variable
+///
+/// Values that are not valid TOML, eg. paths, are read as strings.
+#[must_use]
+pub fn parse_value(value: &str) -> Value {
+    format!("value = {value}")
+        .parse::<Value>()
+        .ok()
+        .and_then(|mut table| table.as_table_mut()?.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+/// Reads a `fleet.toml` layer
+fn read_file(path: &Path, origin: Origin) -> Result<Layer> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    let value = contents
+        .parse::<Value>()
+        .map_err(|err| ConfigError::from_toml(path, &contents, &err))?;
+
+    let values = flatten(&value);
+
+    // The workspace layer is a complete config, members only override some keys
+    let checked = if matches!(origin, Origin::Workspace(_)) {
+        toml::from_str::<FleetConfig>(&contents).map(|_| ())
+    } else {
+        validate(&values)
+    };
+
+    checked.map_err(|err| ConfigError::from_toml(path, &contents, &err))?;
+
+    Ok(Layer { origin, values })
+}
+
+/// Checks the values of a partial layer against the `FleetConfig` schema
+fn validate(values: &BTreeMap<String, Value>) -> Result<(), toml::de::Error> {
+    let mut merged = defaults();
+
+    merged.extend(
+        values
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone())),
+    );
+
+    unflatten(&merged).try_into::<FleetConfig>().map(|_| ())
+}
+
+/// Values of an empty `FleetConfig`, the base every layer is merged onto
+fn defaults() -> BTreeMap<String, Value> {
+    flatten(&Value::try_from(FleetConfig::new()).expect("the default config is serializable"))
+}
+
+/// Returns the directory of the workspace member containing `current_dir`, if it is not the root
+fn package_dir(root_dir: &Path, current_dir: &Path) -> Option<PathBuf> {
+    current_dir
+        .ancestors()
+        .take_while(|dir| *dir != root_dir)
+        .find(|dir| dir.join("Cargo.toml").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Flattens nested tables into dotted keys
+fn flatten(value: &Value) -> BTreeMap<String, Value> {
+    let mut values = BTreeMap::new();
+
+    if let Some(table) = value.as_table() {
+        flatten_into(table, "", &mut values);
+    }
+
+    values
+}
+
+fn flatten_into(table: &Table, prefix: &str, values: &mut BTreeMap<String, Value>) {
+    for (key, value) in table {
+        let key = format!("{prefix}{key}");
+
+        match value {
+            // Empty tables are kept so that required tables are still present once merged
+            Value::Table(table) if !table.is_empty() => {
+                flatten_into(table, &format!("{key}."), values);
+            }
+            value => {
+                values.insert(key, value.clone());
+            }
+        }
+    }
+}
+
+/// Builds nested tables from dotted keys
+fn unflatten(values: &BTreeMap<String, Value>) -> Value {
+    let mut root = Table::new();
+
+    for (key, value) in values {
+        let mut table = &mut root;
+        let mut parts = key.split('.').peekable();
+
+        while let Some(part) = parts.next() {
+            if parts.peek().is_none() {
+                if !(value.is_table() && table.contains_key(part)) {
+                    table.insert(part.to_string(), value.clone());
+                }
+            } else {
+                table = table
+                    .entry(part.to_string())
+                    .or_insert_with(|| Value::Table(Table::new()))
+                    .as_table_mut()
+                    .expect("config keys are either tables or values");
+            }
+        }
+    }
+
+    Value::Table(root)
+}
@@ -0,0 +1,186 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use toml_edit::{Array, Document, Item, TableLike};
+
+/// Name of the sidecar manifest stored next to `fleet.toml`
+pub const MANIFEST_FILE: &str = "fleet-managed.toml";
+
+/// A key of the cargo config that was injected by fleet
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct ManagedKey {
+    /// Path of the key from the root of the cargo config, eg. `["build", "rustc-wrapper"]`
+    pub path: Vec<String>,
+    /// The value the key had before fleet overwrote it, stored as a TOML literal
+    pub previous: Option<String>,
+    /// The elements fleet appended to an array, stored as TOML literals
+    ///
+    /// Arrays are merged rather than replaced, only these elements are removed on restore.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added: Option<Vec<String>>,
+}
+
+/// Represents the `fleet-managed.toml` file
+///
+/// Records every change fleet has made to the repository so that it can be reverted with `fleet eject`.
+#[derive(Deserialize, Debug, Serialize, Clone, Default)]
+pub struct ManagedManifest {
+    /// The cargo config file fleet writes to
+    pub cargo_config: Option<PathBuf>,
+    /// Whether the cargo config file was created by fleet
+    #[serde(default)]
+    pub created_cargo_config: bool,
+    /// Whether `rust-toolchain.toml` was created by fleet
+    #[serde(default)]
+    pub created_toolchain_file: bool,
+    /// The `toolchain.channel` key of `rust-toolchain.toml`, when fleet pinned it
+    pub toolchain: Option<ManagedKey>,
+    /// The ramdisk directory the `target` symlink points to
+    pub ramdisk: Option<PathBuf>,
+    #[serde(default)]
+    pub keys: Vec<ManagedKey>,
+}
+
+impl ManagedManifest {
+    /// Reads the manifest in `dir`, returning an empty manifest if it does not exist
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        toml::from_str(&contents).with_context(|| format!("invalid manifest {}", path.display()))
+    }
+
+    /// Writes the manifest into `dir`
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(MANIFEST_FILE);
+
+        std::fs::write(&path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Removes the manifest from `dir`
+    pub fn remove(dir: &Path) -> Result<()> {
+        let path = dir.join(MANIFEST_FILE);
+
+        if path.exists() {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the recorded key at `path`
+    #[must_use]
+    pub fn key(&self, path: &[String]) -> Option<&ManagedKey> {
+        self.keys.iter().find(|key| key.path == path)
+    }
+}
+
+/// Returns the TOML literal of a value, without any surrounding whitespace or comments
+#[must_use]
+pub fn value_literal(value: &toml_edit::Value) -> String {
+    let mut value = value.clone();
+    value.decor_mut().clear();
+
+    value.to_string().trim().to_string()
+}
+
+/// Removes the `elements` of an array, compared by their TOML literal
+pub fn remove_elements(array: &mut Array, elements: &[String]) {
+    for index in (0..array.len()).rev() {
+        if array
+            .get(index)
+            .is_some_and(|value| elements.contains(&value_literal(value)))
+        {
+            array.remove(index);
+        }
+    }
+}
+
+/// Removes a managed key from the document, restoring the value it had before fleet wrote it
+///
+/// Only the elements fleet added are removed from a merged array. Tables that are left empty by
+/// the removal are removed as well.
+pub fn restore_key(document: &mut Document, key: &ManagedKey) {
+    restore_in(document.as_item_mut(), &key.path, key);
+}
+
+fn restore_in(item: &mut Item, path: &[String], key: &ManagedKey) {
+    let Some((name, rest)) = path.split_first() else {
+        return;
+    };
+
+    let Some(table) = item.as_table_like_mut() else {
+        return;
+    };
+
+    if rest.is_empty() {
+        if let Some(added) = &key.added {
+            if let Some(array) = table.get_mut(name).and_then(Item::as_array_mut) {
+                remove_elements(array, added);
+
+                // The array was created by fleet, it is removed with its last element
+                if array.is_empty() && key.previous.is_none() {
+                    table.remove(name);
+                }
+            }
+
+            return;
+        }
+
+        let previous = key
+            .previous
+            .as_deref()
+            .and_then(|previous| previous.parse::<toml_edit::Value>().ok());
+
+        match (previous, table.get_mut(name)) {
+            (Some(mut previous), Some(existing)) => {
+                if let Some(existing_value) = existing.as_value() {
+                    *previous.decor_mut() = existing_value.decor().clone();
+                }
+
+                *existing = Item::Value(previous);
+            }
+            (Some(previous), None) => {
+                table.insert(name, Item::Value(previous));
+            }
+            (None, _) => {
+                table.remove(name);
+            }
+        }
+
+        return;
+    }
+
+    if let Some(child) = table.get_mut(name) {
+        restore_in(child, rest, key);
+
+        if child.as_table_like().is_some_and(TableLike::is_empty) {
+            table.remove(name);
+        }
+    }
+}
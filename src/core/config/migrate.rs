@@ -0,0 +1,174 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use toml_edit::{value, Document};
+
+/// Version of the `fleet.toml` layout written by this version of fleet
+///
+/// Files without a `schema_version` key predate versioning and are version 0.
+pub const SCHEMA_VERSION: i64 = 1;
+
+/// Upgrades a document from the version at the same index to the next version
+///
+/// A migration is added here every time the layout of `fleet.toml` changes in a way that is not
+/// covered by `#[serde(default)]`, eg. when a key is renamed or moved.
+const MIGRATIONS: &[fn(&mut Document)] = &[v0_to_v1];
+
+/// Version 1 introduces `schema_version` and starts reading `rd_enabled`
+///
+/// Version 0 files were generated with `rd_enabled = true` although the key was never read, it is
+/// removed so that the ramdisk stays opt-in.
+fn v0_to_v1(document: &mut Document) {
+    document.remove("rd_enabled");
+}
+
+/// Upgrades the `fleet.toml` at `path` in place to [`SCHEMA_VERSION`]
+///
+/// The original file is kept next to it as `fleet.toml.v{version}.bak`.
+/// Returns `true` if the file was migrated.
+pub fn migrate(path: &Path) -> Result<bool> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    let Ok(mut document) = contents.parse::<Document>() else {
+        // Syntax errors are reported with their location once the file is read
+        return Ok(false);
+    };
+
+    let version = match document.get("schema_version") {
+        None => 0,
+        Some(item) => match item.as_integer() {
+            Some(version) if version >= 0 => version,
+            _ => bail!(
+                "invalid fleet configuration in {}: `schema_version` must be a non-negative integer",
+                path.display()
+            ),
+        },
+    };
+
+    if version > SCHEMA_VERSION {
+        bail!(
+            "{} uses schema version {version} but this version of fleet only supports up to version {SCHEMA_VERSION}, please update fleet",
+            path.display()
+        );
+    }
+
+    if version == SCHEMA_VERSION {
+        return Ok(false);
+    }
+
+    let backup = path.with_extension(format!("toml.v{version}.bak"));
+
+    std::fs::write(&backup, &contents)
+        .with_context(|| format!("failed to write {}", backup.display()))?;
+
+    let start = usize::try_from(version).unwrap_or_default();
+
+    for migration in &MIGRATIONS[start..] {
+        migration(&mut document);
+    }
+
+    if let Some(item) = document.get_mut("schema_version") {
+        *item = value(SCHEMA_VERSION);
+    } else {
+        // Keeps the version at the top of the file
+        document = format!("schema_version = {SCHEMA_VERSION}\n{document}")
+            .parse::<Document>()
+            .context("failed to add `schema_version`")?;
+    }
+
+    std::fs::write(path, document.to_string())
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    println!(
+        "⬆️  Migrated {} to schema version {SCHEMA_VERSION}, the original file was saved to {}",
+        path.display(),
+        backup.display()
+    );
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migrate, SCHEMA_VERSION};
+
+    #[test]
+    fn migrates_a_v0_file_and_keeps_a_backup() {
+        let dir = std::env::temp_dir().join(format!("fleet-migrate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("fleet.toml");
+        let original = "fleet_id = \"test\"\n\n[build]\nsccache = \"sccache\"\n";
+
+        std::fs::write(&path, original).unwrap();
+
+        let migrated = migrate(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let backup = std::fs::read_to_string(dir.join("fleet.toml.v0.bak"));
+        let again = migrate(&path).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(migrated);
+        assert!(!again);
+        assert_eq!(backup.unwrap(), original);
+        assert_eq!(
+            contents,
+            format!("schema_version = {SCHEMA_VERSION}\n{original}")
+        );
+    }
+
+    #[test]
+    fn v0_files_do_not_enable_the_ramdisk() {
+        let dir =
+            std::env::temp_dir().join(format!("fleet-migrate-ramdisk-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("fleet.toml");
+        std::fs::write(&path, "rd_enabled = true\nfleet_id = \"test\"\n").unwrap();
+
+        migrate(&path).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            contents,
+            format!("schema_version = {SCHEMA_VERSION}\nfleet_id = \"test\"\n")
+        );
+    }
+
+    #[test]
+    fn rejects_a_negative_version() {
+        let dir =
+            std::env::temp_dir().join(format!("fleet-migrate-negative-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("fleet.toml");
+        std::fs::write(&path, "schema_version = -1\n").unwrap();
+
+        let error = migrate(&path).unwrap_err();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(error.to_string().contains("a non-negative integer"));
+    }
+}
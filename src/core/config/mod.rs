@@ -18,13 +18,41 @@
 /// Handles configuration of the fleet setup and execution
 pub mod cargo;
 pub mod enable;
+pub mod error;
 pub mod global;
+pub mod layered;
+pub mod managed;
+pub mod migrate;
+pub mod toolchain;
 
-use global::FleetGlobalConfig;
+use anyhow::{bail, Context, Result};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::{path::PathBuf, process::exit};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml_edit::{table, value, Document};
 use which::which;
 
+/// Name of the config file of a fleet project
+pub const CONFIG_FILE: &str = "fleet.toml";
+
+/// Every key of `fleet.toml`, as dotted paths
+pub const KEYS: &[&str] = &[
+    "rd_enabled",
+    "rd_size_limit",
+    "rd_snapshot",
+    "fleet_id",
+    "toolchain",
+    "build.linker",
+    "build.sccache",
+    "build.lld",
+    "build.clang",
+    "build.zld",
+    "build.mold",
+    "profile.dev",
+    "profile.release",
+];
+
 /// Finds the path of a binary
 ///
 /// Finds the path of a binary and returns the path if it exists
@@ -37,21 +65,167 @@ pub fn find(bin: &str) -> Option<PathBuf> {
     }
 }
 
+/// Finds the root of the project containing `dir`
+///
+/// The root is the closest directory whose `Cargo.toml` declares a `[workspace]`, or the closest
+/// directory with a `Cargo.toml` when the package is not part of a workspace.
+#[must_use]
+pub fn find_root(dir: &Path) -> Option<PathBuf> {
+    let mut package = None;
+
+    for ancestor in dir.ancestors() {
+        let Ok(contents) = std::fs::read_to_string(ancestor.join("Cargo.toml")) else {
+            continue;
+        };
+
+        let is_workspace = contents
+            .parse::<Document>()
+            .is_ok_and(|manifest| manifest.contains_key("workspace"));
+
+        if is_workspace {
+            return Some(ancestor.to_path_buf());
+        }
+
+        package.get_or_insert_with(|| ancestor.to_path_buf());
+    }
+
+    package
+}
+
+/// Reads `fleet_id` from the `fleet.toml` at `root`, without validating the rest of the file
+#[must_use]
+pub fn read_fleet_id(root: &Path) -> Option<String> {
+    let contents = std::fs::read_to_string(root.join(CONFIG_FILE)).ok()?;
+    let config = contents.parse::<toml::Value>().ok()?;
+
+    config.get("fleet_id")?.as_str().map(ToString::to_string)
+}
+
+/// Linker used to link the project
+#[derive(Deserialize, Debug, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Linker {
+    Lld,
+    Mold,
+    Zld,
+    /// The default linker of the platform
+    System,
+}
+
+impl Linker {
+    /// Name of the linker, as written in `fleet.toml`
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Linker::Lld => "lld",
+            Linker::Mold => "mold",
+            Linker::Zld => "zld",
+            Linker::System => "system",
+        }
+    }
+}
+
+/// Optimization preset applied to a cargo profile
+#[derive(Deserialize, Debug, Serialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// Fast incremental builds, keeps the optimization level and debug info of the profile
+    FastDev,
+    /// Clean builds without incremental compilation or debug info
+    Ci,
+    /// Fastest binaries, with fat LTO and a single codegen unit
+    MaxPerf,
+    /// Smallest binaries, optimized for size
+    SmallBinary,
+    /// The profile is left untouched
+    None,
+}
+
 /// Represents the build table of the `fleet.toml` file
-#[derive(Deserialize, Debug, Serialize, Clone)]
+#[derive(Deserialize, Debug, Serialize, JsonSchema, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
 pub struct Build {
+    /// The linker selected with `fleet configure`, the fastest available linker is used when unset
+    pub linker: Option<Linker>,
+    /// Path of the `sccache` binary, used as the rustc wrapper
     pub sccache: Option<PathBuf>,
+    /// Path of the `lld` binary
     pub lld: Option<PathBuf>,
+    /// Path of the `clang` binary, used as the linker driver on Linux
     pub clang: Option<PathBuf>,
+    /// Path of the `zld` binary
     pub zld: Option<PathBuf>,
+    /// Path of the `mold` binary
+    pub mold: Option<PathBuf>,
+}
+
+impl From<global::Build> for Build {
+    fn from(build: global::Build) -> Self {
+        Self {
+            linker: None,
+            sccache: build.sccache,
+            lld: build.lld,
+            clang: build.clang,
+            zld: build.zld,
+            mold: build.mold,
+        }
+    }
+}
+
+/// Parses a size such as `512 MiB` or `4GB` into bytes
+fn parse_size(size: &str) -> Option<u64> {
+    let bytes = byte_unit::Byte::from_str(size).ok()?.get_bytes();
+
+    Some(u64::try_from(bytes).unwrap_or(u64::MAX))
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let size = String::deserialize(deserializer)?;
+
+    if parse_size(&size).is_none() {
+        return Err(serde::de::Error::custom(format!(
+            "invalid size `{size}`, expected eg. \"4 GiB\""
+        )));
+    }
+
+    Ok(Some(size))
 }
 
 /// Represents the `fleet.toml` file
-#[derive(Deserialize, Debug, Serialize, Clone)]
+// Fields added to the schema must have a default, so that older files still deserialize.
+#[derive(Deserialize, Debug, Serialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct FleetConfig {
+    /// Version of the layout of the file
+    #[serde(default)]
+    pub schema_version: i64,
+    /// Whether the target directory is moved to a ramdisk, opt-in
+    #[serde(default)]
     pub rd_enabled: bool,
+    /// Largest size the ramdisk of the project may reach, eg. `"4 GiB"`, unlimited unless set
+    ///
+    /// The project builds on disk once its ramdisk is over the limit.
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub rd_size_limit: Option<String>,
+    /// Whether the artifacts of the ramdisk are saved to disk after each build, and restored to an
+    /// empty ramdisk, eg. after a reboot
+    #[serde(default)]
+    pub rd_snapshot: bool,
+    /// Required, the ramdisk of the project is named after it
     pub fleet_id: String,
+    /// Toolchain pinned for the project in `rust-toolchain.toml`, eg. `nightly`
+    #[serde(default)]
+    pub toolchain: Option<String>,
+    #[serde(default)]
     pub build: Build,
+    /// Preset applied to each cargo profile, eg. `dev = "fast-dev"`
+    ///
+    /// `dev` uses `fast-dev` unless set, other profiles are left untouched.
+    #[serde(default)]
+    pub profile: BTreeMap<String, Preset>,
 }
 
 impl Default for FleetConfig {
@@ -65,77 +239,112 @@ impl FleetConfig {
     #[must_use]
     pub fn new() -> Self {
         Self {
+            schema_version: migrate::SCHEMA_VERSION,
             rd_enabled: false,
+            rd_size_limit: None,
+            rd_snapshot: false,
             fleet_id: String::from(""),
+            toolchain: None,
             build: Build {
+                linker: None,
                 sccache: None,
                 lld: None,
                 clang: None,
                 zld: None,
+                mold: None,
             },
+            profile: BTreeMap::new(),
         }
     }
 
-    /// Creates and read the `fleet.toml` file
-    ///
-    ///
-    /// If the fleet.toml does not exist, it is created with the basic settings and the basic config is returned.
-    ///
-    /// If it does exist, the `fleet.toml` file is read and the data is parsed into `FleetConfig` and returned.
-    ///
-    /// When a particular field of the `build` table is empty, it is substituted with the value from the global fleet config.
-    ///
-    /// T
-    /// # Panics
-    /// Can panic if cannot find home directory
+    /// Returns the preset of every cargo profile fleet configures
     #[must_use]
-    pub fn run_config() -> Self {
-        let global_config = FleetGlobalConfig::run_config();
-
-        let config_path = std::env::current_dir()
-            .expect("cannot find current directory")
-            .join("fleet.toml");
-
-        if config_path.exists() {
-            let config_file = std::fs::read_to_string(config_path).unwrap();
-            let config = toml::from_str::<Self>(&config_file);
-
-            if let Ok(mut config) = config {
-                if config.build.sccache.is_none() {
-                    config.build.sccache = global_config.build.sccache;
-                }
-
-                if config.build.lld.is_none() {
-                    config.build.lld = global_config.build.lld;
-                }
-
-                if config.build.clang.is_none() {
-                    config.build.clang = global_config.build.clang;
-                }
-
-                if config.build.zld.is_none() {
-                    config.build.zld = global_config.build.zld;
-                }
-
-                config
-            } else {
-                eprintln!("Invalid fleet configuration");
-                exit(1)
-            }
-        } else {
-            let config = FleetConfig {
-                rd_enabled: true,
-                fleet_id: uuid::Uuid::new_v4().to_string(),
-                build: Build {
-                    sccache: None,
-                    lld: None,
-                    clang: None,
-                    zld: None,
+    pub fn presets(&self) -> BTreeMap<String, Preset> {
+        let mut presets = BTreeMap::from([(String::from("dev"), Preset::FastDev)]);
+
+        presets.extend(self.profile.clone());
+
+        presets
+    }
+
+    /// Returns `rd_size_limit` in bytes
+    #[must_use]
+    pub fn rd_size_limit(&self) -> Option<u64> {
+        self.rd_size_limit.as_deref().and_then(parse_size)
+    }
+
+    /// Creates a new config for a project, with the tools found on the system
+    #[must_use]
+    pub fn detect() -> Self {
+        Self {
+            schema_version: migrate::SCHEMA_VERSION,
+            rd_enabled: false,
+            rd_size_limit: None,
+            rd_snapshot: false,
+            fleet_id: uuid::Uuid::new_v4().to_string(),
+            toolchain: None,
+            build: Build {
+                linker: None,
+                sccache: find("sccache"),
+                lld: if cfg!(windows) {
+                    Some(PathBuf::from("rust-lld.exe"))
+                } else {
+                    find("ld.lld")
                 },
-            };
-            let config_file = toml::to_string(&config).unwrap();
-            std::fs::write(config_path, config_file).unwrap();
-            config
+                clang: find("clang"),
+                zld: find("zld"),
+                mold: find("mold"),
+            },
+            profile: BTreeMap::from([(String::from("dev"), Preset::FastDev)]),
         }
     }
+
+    /// Creates the `fleet.toml` file of the project at `root` with the basic settings
+    pub fn create(root: &Path) -> Result<()> {
+        let path = root.join(CONFIG_FILE);
+
+        let config = FleetConfig {
+            fleet_id: uuid::Uuid::new_v4().to_string(),
+            ..Self::new()
+        };
+
+        std::fs::write(&path, toml::to_string(&config)?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+
+        println!("📝 Generated fleet.toml");
+
+        Ok(())
+    }
+
+    /// Saves the linker and the `paths` of its binaries into the `build` table of the `fleet.toml`
+    /// file in `dir`, `paths` are keyed by their name in the table
+    ///
+    /// The rest of the file is kept as is.
+    pub fn save_linker(dir: &Path, linker: Linker, paths: &[(&str, PathBuf)]) -> Result<()> {
+        let path = dir.join(CONFIG_FILE);
+
+        if !path.exists() {
+            bail!("no fleet.toml found, run `fleet init` first");
+        }
+
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        let mut document = contents
+            .parse::<Document>()
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+
+        if !document.contains_key("build") {
+            document.insert("build", table());
+        }
+
+        document["build"]["linker"] = value(linker.as_str());
+
+        for (key, path) in paths {
+            document["build"][key] = value(path.display().to_string());
+        }
+
+        std::fs::write(&path, document.to_string())
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
 }
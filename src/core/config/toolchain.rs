@@ -0,0 +1,90 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use crate::core::config::managed::{value_literal, ManagedKey, ManagedManifest};
+use anyhow::{Context, Result};
+use rustc_version::Channel;
+use std::path::Path;
+use toml_edit::{value, Document, Item, Table};
+
+/// Name of the file used by rustup to pin the toolchain of a project
+pub const TOOLCHAIN_FILE: &str = "rust-toolchain.toml";
+
+/// Checks whether the active toolchain is a nightly toolchain
+///
+/// The toolchain is resolved by running `rustc -vV` in the current directory, so a pinned
+/// `rust-toolchain.toml` is taken into account.
+#[must_use]
+pub fn is_nightly() -> bool {
+    rustc_version::version_meta().is_ok_and(|meta| meta.channel == Channel::Nightly)
+}
+
+/// Pins the toolchain of the project in `dir` by writing `rust-toolchain.toml`
+///
+/// Only the `toolchain.channel` key is updated, the rest of an existing file is preserved. The
+/// created file or the previous channel is recorded in `manifest` so that eject can revert it.
+pub fn pin_toolchain(dir: &Path, channel: &str, manifest: &mut ManagedManifest) -> Result<()> {
+    let path = dir.join(TOOLCHAIN_FILE);
+    let created = !path.exists();
+
+    let contents = if created {
+        String::new()
+    } else {
+        std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?
+    };
+
+    let mut document = contents
+        .parse::<Document>()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    let previous = document
+        .get("toolchain")
+        .and_then(|toolchain| toolchain.get("channel"))
+        .and_then(Item::as_value);
+
+    if previous.and_then(toml_edit::Value::as_str) == Some(channel) {
+        return Ok(());
+    }
+
+    // The channel the user pinned is only recorded the first time fleet overwrites it
+    if manifest.toolchain.is_none() {
+        manifest.toolchain = Some(ManagedKey {
+            path: vec!["toolchain".to_string(), "channel".to_string()],
+            previous: previous.map(value_literal),
+            added: None,
+        });
+    }
+
+    manifest.created_toolchain_file |= created;
+
+    if !document.contains_table("toolchain") {
+        let mut toolchain = Table::new();
+        toolchain.decor_mut().set_prefix("");
+
+        document.insert("toolchain", Item::Table(toolchain));
+    }
+
+    document["toolchain"]["channel"] = value(channel);
+
+    std::fs::write(&path, document.to_string())
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    println!("📌 Pinned {channel} toolchain");
+
+    Ok(())
+}
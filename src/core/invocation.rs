@@ -0,0 +1,629 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+
+/// Message format passed to cargo unless [`CargoInvocation::message_format`] changes it
+///
+/// Cargo renders diagnostics on stderr itself, stdout only holds JSON messages.
+pub const MESSAGE_FORMAT: &str = "json-render-diagnostics";
+
+/// The id of a package in cargo messages
+///
+/// Older versions of cargo use `name version (source)`, newer ones `source#name@version`, or
+/// `source#version` when the name is the last segment of the source.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct PackageId(pub String);
+
+impl PackageId {
+    /// Returns the name and version of the package
+    #[must_use]
+    pub fn name_and_version(&self) -> (&str, &str) {
+        let id = self.0.as_str();
+
+        // Sources of the older format may hold a `#` as well, eg. git revisions
+        if let Some((source, spec)) = id.rsplit_once('#').filter(|_| !id.contains(' ')) {
+            if let Some(name_and_version) = spec.split_once('@') {
+                return name_and_version;
+            }
+
+            let path = source.split('?').next().unwrap_or(source);
+            let name = path
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .unwrap_or(path);
+
+            return (name, spec);
+        }
+
+        let mut parts = id.split(' ');
+
+        (parts.next().unwrap_or(id), parts.next().unwrap_or_default())
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.name_and_version().0
+    }
+
+    #[must_use]
+    pub fn version(&self) -> &str {
+        self.name_and_version().1
+    }
+}
+
+/// A target of a package, eg. a binary or a library
+#[derive(Deserialize, Debug, Clone)]
+pub struct Target {
+    pub name: String,
+    /// Kinds of the target, eg. `bin`, `lib` or `custom-build`
+    pub kind: Vec<String>,
+    pub src_path: PathBuf,
+}
+
+/// A `compiler-artifact` message, sent once a target has been built
+#[derive(Deserialize, Debug, Clone)]
+pub struct Artifact {
+    pub package_id: PackageId,
+    pub target: Target,
+    pub filenames: Vec<PathBuf>,
+    /// Path of the binary, for executable targets
+    pub executable: Option<PathBuf>,
+    /// Whether the target was up to date and not rebuilt
+    pub fresh: bool,
+}
+
+/// A diagnostic emitted by the compiler
+#[derive(Deserialize, Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    /// Level of the diagnostic, eg. `warning` or `error`
+    pub level: String,
+    /// The diagnostic as the compiler would print it
+    pub rendered: Option<String>,
+}
+
+/// A `compiler-message` message, sent for diagnostics cargo did not render itself
+#[derive(Deserialize, Debug, Clone)]
+pub struct CompilerMessage {
+    pub package_id: PackageId,
+    pub target: Target,
+    pub message: Diagnostic,
+}
+
+/// A `build-script-executed` message, sent once a build script has run
+#[derive(Deserialize, Debug, Clone)]
+pub struct BuildScript {
+    pub package_id: PackageId,
+    pub out_dir: PathBuf,
+    #[serde(default)]
+    pub linked_libs: Vec<String>,
+}
+
+/// A JSON message of cargo, see <https://doc.rust-lang.org/cargo/reference/external-tools.html#json-messages>
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum Message {
+    CompilerArtifact(Artifact),
+    CompilerMessage(CompilerMessage),
+    BuildScriptExecuted(BuildScript),
+    BuildFinished {
+        success: bool,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Something cargo reported while it runs
+#[derive(Debug, Clone)]
+pub enum CargoEvent {
+    Artifact(Artifact),
+    Message(CompilerMessage),
+    BuildScript(BuildScript),
+    /// The build is over, the command may still run afterwards, eg. `cargo run`
+    Finished {
+        success: bool,
+    },
+    /// A line of stdout that is not a cargo message, eg. the output of a cargo plugin
+    Stdout(String),
+    /// A line of stderr, only sent when it is captured
+    Stderr(String),
+}
+
+impl CargoEvent {
+    /// Prints the event as cargo would have, messages other than diagnostics are not printed
+    pub fn print(&self) {
+        match self {
+            Self::Stdout(line) => println!("{line}"),
+            Self::Stderr(line) => eprintln!("{line}"),
+            Self::Message(message) => {
+                if let Some(rendered) = &message.message.rendered {
+                    eprint!("{rendered}");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Parses a line of stdout, `None` for messages fleet does not handle
+    fn from_stdout(line: String) -> Option<Self> {
+        if !line.starts_with("{\"reason\":") {
+            return Some(Self::Stdout(line));
+        }
+
+        match serde_json::from_str::<Message>(&line) {
+            Ok(Message::CompilerArtifact(artifact)) => Some(Self::Artifact(artifact)),
+            Ok(Message::CompilerMessage(message)) => Some(Self::Message(message)),
+            Ok(Message::BuildScriptExecuted(script)) => Some(Self::BuildScript(script)),
+            Ok(Message::BuildFinished { success }) => Some(Self::Finished { success }),
+            Ok(Message::Other) => None,
+            Err(_) => Some(Self::Stdout(line)),
+        }
+    }
+}
+
+/// A cargo command whose output is parsed into [`CargoEvent`]s
+///
+/// Commands that print JSON of their own, eg. `cargo bloat`, run without a message format and
+/// their output is sent as [`CargoEvent::Stdout`].
+#[derive(Debug, Clone)]
+pub struct CargoInvocation {
+    subcommand: String,
+    args: Vec<String>,
+    message_format: Option<String>,
+    capture_stderr: bool,
+}
+
+impl CargoInvocation {
+    /// Creates an invocation of `cargo <subcommand>` using [`MESSAGE_FORMAT`]
+    #[must_use]
+    pub fn new(subcommand: &str) -> Self {
+        Self {
+            subcommand: subcommand.to_string(),
+            args: Vec::new(),
+            message_format: Some(MESSAGE_FORMAT.to_string()),
+            capture_stderr: false,
+        }
+    }
+
+    pub fn arg(&mut self, arg: impl Into<String>) -> &mut Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets the `--message-format` passed to cargo, `None` for commands that don't accept it
+    pub fn message_format(&mut self, format: Option<&str>) -> &mut Self {
+        self.message_format = format.map(ToString::to_string);
+        self
+    }
+
+    /// Sends stderr as [`CargoEvent::Stderr`] instead of printing it
+    pub fn capture_stderr(&mut self, capture: bool) -> &mut Self {
+        self.capture_stderr = capture;
+        self
+    }
+
+    /// Returns the arguments passed to cargo, the message format goes before any `--`
+    #[must_use]
+    pub fn command_args(&self) -> Vec<String> {
+        let mut args = vec![self.subcommand.clone()];
+        let separator = self.args.iter().position(|arg| arg == "--");
+        let (before, after) = self.args.split_at(separator.unwrap_or(self.args.len()));
+
+        args.extend(before.iter().cloned());
+
+        // An explicit `--message-format` is left to the caller
+        let explicit = before.iter().any(|arg| arg.starts_with("--message-format"));
+
+        if let Some(format) = self.message_format.as_ref().filter(|_| !explicit) {
+            args.push(format!("--message-format={format}"));
+        }
+
+        args.extend(after.iter().cloned());
+
+        args
+    }
+
+    /// Runs cargo and calls `on_event` for everything it reports, until it exits
+    ///
+    /// stdin is inherited, stderr too unless it is captured.
+    pub fn run(&self, mut on_event: impl FnMut(CargoEvent)) -> Result<ExitStatus> {
+        let mut child = Command::new("cargo")
+            .args(self.command_args())
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::piped())
+            .stderr(if self.capture_stderr {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
+            })
+            .spawn()
+            .with_context(|| format!("failed to run cargo {}", self.subcommand))?;
+
+        let (sender, receiver) = mpsc::channel();
+
+        let readers: Vec<_> = [
+            child
+                .stdout
+                .take()
+                .map(|stdout| read_lines(stdout, sender.clone(), CargoEvent::from_stdout)),
+            child.stderr.take().map(|stderr| {
+                read_lines(stderr, sender.clone(), |line| {
+                    Some(CargoEvent::Stderr(line))
+                })
+            }),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        // The channel closes once both readers are done
+        drop(sender);
+
+        for event in receiver {
+            on_event(event);
+        }
+
+        for reader in readers {
+            let _ = reader.join();
+        }
+
+        child
+            .wait()
+            .with_context(|| format!("failed to wait for cargo {}", self.subcommand))
+    }
+}
+
+/// Sends every line of `stream` through `sender` from a new thread
+fn read_lines<R, F>(
+    stream: R,
+    sender: mpsc::Sender<CargoEvent>,
+    parse: F,
+) -> std::thread::JoinHandle<()>
+where
+    R: Read + Send + 'static,
+    F: Fn(String) -> Option<CargoEvent> + Send + 'static,
+{
+    std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines().map_while(Result::ok) {
+            if let Some(event) = parse(line) {
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        }
+    })
+}
+
+/// Output of `cargo build --unit-graph`
+#[derive(Deserialize)]
+struct UnitGraph {
+    units: Vec<serde_json::Value>,
+}
+
+/// The parts of `cargo metadata` needed to estimate the units of a build
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+    workspace_members: Vec<String>,
+    workspace_default_members: Option<Vec<String>>,
+    resolve: Option<Resolve>,
+}
+
+#[derive(Deserialize)]
+struct MetadataPackage {
+    id: String,
+    targets: Vec<MetadataTarget>,
+}
+
+#[derive(Deserialize)]
+struct MetadataTarget {
+    kind: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Resolve {
+    nodes: Vec<ResolveNode>,
+    root: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResolveNode {
+    id: String,
+    deps: Vec<ResolveDep>,
+}
+
+#[derive(Deserialize)]
+struct ResolveDep {
+    pkg: String,
+    dep_kinds: Vec<DepKind>,
+}
+
+#[derive(Deserialize)]
+struct DepKind {
+    /// `None` for normal dependencies, `dev` or `build` otherwise
+    kind: Option<String>,
+}
+
+/// Estimates the units of a build from the dependency graph of `metadata`
+///
+/// Each dependency builds its library and, with a build script, compiles and runs it. The
+/// packages being built also build their binaries.
+fn estimate_units(metadata: &Metadata) -> u64 {
+    let Some(resolve) = &metadata.resolve else {
+        return 0;
+    };
+
+    let roots: Vec<&String> = match &resolve.root {
+        Some(root) => vec![root],
+        None => metadata
+            .workspace_default_members
+            .as_ref()
+            .unwrap_or(&metadata.workspace_members)
+            .iter()
+            .collect(),
+    };
+
+    let nodes: std::collections::HashMap<&str, &ResolveNode> = resolve
+        .nodes
+        .iter()
+        .map(|node| (node.id.as_str(), node))
+        .collect();
+
+    let packages: std::collections::HashMap<&str, &MetadataPackage> = metadata
+        .packages
+        .iter()
+        .map(|package| (package.id.as_str(), package))
+        .collect();
+
+    let mut seen: std::collections::HashSet<&str> = std::collections::HashSet::new();
+    let mut queue: Vec<&str> = roots.iter().map(|root| root.as_str()).collect();
+    let mut units = 0;
+
+    while let Some(id) = queue.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+
+        if let Some(package) = packages.get(id) {
+            let has_kind = |kinds: &[&str]| {
+                package
+                    .targets
+                    .iter()
+                    .filter(|target| {
+                        target
+                            .kind
+                            .iter()
+                            .any(|kind| kinds.contains(&kind.as_str()))
+                    })
+                    .count() as u64
+            };
+
+            units +=
+                has_kind(&["lib", "rlib", "dylib", "cdylib", "staticlib", "proc-macro"]).min(1);
+            units += has_kind(&["custom-build"]) * 2;
+
+            if roots.iter().any(|root| *root == id) {
+                units += has_kind(&["bin"]);
+            }
+        }
+
+        if let Some(node) = nodes.get(id) {
+            queue.extend(
+                node.deps
+                    .iter()
+                    .filter(|dep| {
+                        dep.dep_kinds
+                            .iter()
+                            .any(|kind| kind.kind.as_deref() != Some("dev"))
+                    })
+                    .map(|dep| dep.pkg.as_str()),
+            );
+        }
+    }
+
+    units
+}
+
+/// Counts the units cargo builds with `args`, the arguments of `cargo build`
+///
+/// The unit graph is exact but only available on nightly, elsewhere the units are estimated from
+/// `cargo metadata`. Returns `None` when neither can be read.
+#[must_use]
+pub fn unit_count(args: &[&str], target: Option<&str>, nightly: bool) -> Option<u64> {
+    if nightly {
+        let output = Command::new("cargo")
+            .arg("build")
+            .args(args)
+            .args(["--unit-graph", "-Z", "unstable-options"])
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+
+        return units_of_graph(&output.stdout);
+    }
+
+    let mut command = Command::new("cargo");
+
+    command.args(["metadata", "--format-version", "1"]);
+
+    if let Some(target) = target {
+        command.args(["--filter-platform", target]);
+    }
+
+    let output = command
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    units_of_metadata(&output.stdout)
+}
+
+/// Counts the units of the output of `cargo build --unit-graph`
+fn units_of_graph(output: &[u8]) -> Option<u64> {
+    let graph: UnitGraph = serde_json::from_slice(output).ok()?;
+
+    Some(graph.units.len() as u64)
+}
+
+/// Estimates the units of a build from the output of `cargo metadata`, `None` if there are none
+fn units_of_metadata(output: &[u8]) -> Option<u64> {
+    let metadata: Metadata = serde_json::from_slice(output).ok()?;
+
+    Some(estimate_units(&metadata)).filter(|units| *units > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{units_of_graph, units_of_metadata, CargoInvocation, PackageId, MESSAGE_FORMAT};
+
+    fn id(id: &str) -> PackageId {
+        PackageId(id.to_string())
+    }
+
+    #[test]
+    fn old_package_ids() {
+        assert_eq!(
+            id("serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)")
+                .name_and_version(),
+            ("serde", "1.0.0")
+        );
+        assert_eq!(
+            id("fleet 0.1.0 (git+https://github.com/dimensionhq/fleet#0123abcd)")
+                .name_and_version(),
+            ("fleet", "0.1.0")
+        );
+        assert_eq!(
+            id("app 0.1.0 (path+file:///home/user/app)").name_and_version(),
+            ("app", "0.1.0")
+        );
+    }
+
+    #[test]
+    fn new_package_ids() {
+        assert_eq!(
+            id("registry+https://github.com/rust-lang/crates.io-index#serde@1.0.0")
+                .name_and_version(),
+            ("serde", "1.0.0")
+        );
+        assert_eq!(
+            id("path+file:///home/user/crates/core#app-core@0.1.0").name_and_version(),
+            ("app-core", "0.1.0")
+        );
+        assert_eq!(
+            id("path+file:///home/user/app#0.1.0").name_and_version(),
+            ("app", "0.1.0")
+        );
+        assert_eq!(
+            id("git+https://github.com/dimensionhq/fleet?branch=main#0.1.0").name_and_version(),
+            ("fleet", "0.1.0")
+        );
+    }
+
+    #[test]
+    fn message_format_goes_before_the_separator() {
+        let format = format!("--message-format={MESSAGE_FORMAT}");
+
+        assert_eq!(
+            CargoInvocation::new("run")
+                .args(["--release", "--", "--message-format=short"])
+                .command_args(),
+            ["run", "--release", &format, "--", "--message-format=short"]
+        );
+        assert_eq!(
+            CargoInvocation::new("build").command_args(),
+            ["build", &format]
+        );
+    }
+
+    #[test]
+    fn explicit_message_format_is_kept() {
+        assert_eq!(
+            CargoInvocation::new("build")
+                .arg("--message-format=short")
+                .command_args(),
+            ["build", "--message-format=short"]
+        );
+        assert_eq!(
+            CargoInvocation::new("bloat")
+                .message_format(None)
+                .args(["--", "x"])
+                .command_args(),
+            ["bloat", "--", "x"]
+        );
+    }
+
+    #[test]
+    fn unit_graph_is_counted() {
+        let graph =
+            br#"{"version":1,"units":[{"pkg_id":"a"},{"pkg_id":"b"},{"pkg_id":"c"}],"roots":[2]}"#;
+
+        assert_eq!(units_of_graph(graph), Some(3));
+        assert_eq!(units_of_graph(b"error"), None);
+    }
+
+    #[test]
+    fn units_are_estimated_from_metadata() {
+        let metadata = br#"{
+            "packages": [
+                {"id": "app", "targets": [{"kind": ["lib"]}, {"kind": ["bin"]}, {"kind": ["bin"]}]},
+                {"id": "serde", "targets": [{"kind": ["lib"]}, {"kind": ["custom-build"]}]},
+                {"id": "cc", "targets": [{"kind": ["lib"]}, {"kind": ["bin"]}]},
+                {"id": "tempfile", "targets": [{"kind": ["lib"]}]}
+            ],
+            "workspace_members": ["app"],
+            "resolve": {
+                "root": "app",
+                "nodes": [
+                    {"id": "app", "deps": [
+                        {"pkg": "serde", "dep_kinds": [{"kind": null}]},
+                        {"pkg": "cc", "dep_kinds": [{"kind": "build"}]},
+                        {"pkg": "tempfile", "dep_kinds": [{"kind": "dev"}]}
+                    ]},
+                    {"id": "serde", "deps": [{"pkg": "cc", "dep_kinds": [{"kind": "build"}]}]},
+                    {"id": "cc", "deps": []},
+                    {"id": "tempfile", "deps": []}
+                ]
+            }
+        }"#;
+
+        // app: lib and 2 bins, serde: lib and build script, cc: lib only, tempfile is a dev dependency
+        assert_eq!(units_of_metadata(metadata), Some(7));
+        assert_eq!(
+            units_of_metadata(br#"{"packages": [], "workspace_members": [], "resolve": null}"#),
+            None
+        );
+    }
+}
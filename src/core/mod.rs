@@ -17,3 +17,5 @@
 
 pub mod commands;
 pub mod config;
+pub mod invocation;
+pub mod ramdisk;
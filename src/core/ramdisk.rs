@@ -0,0 +1,468 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use crate::cli::prompt::prompts::Confirm;
+use crate::core::config::FleetConfig;
+use crate::utils::disk::{
+    available_space, dir_size, is_non_empty_dir, is_owned, move_dir, sync_dir,
+};
+use anyhow::{bail, Context, Result};
+use byte_unit::Byte;
+use std::borrow::Cow;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Directory holding the ramdisk of every project, a tmpfs on Linux
+pub const RAMDISK_ROOT: &str = "/dev/shm";
+
+/// File in each ramdisk holding the path of its project, touched every time the ramdisk is used
+pub const PROJECT_FILE: &str = ".fleet-project";
+
+/// Directories of a cargo profile saved in snapshots, the rest is quick to rebuild
+const SNAPSHOT_DIRS: &[&str] = &[".fingerprint", "build", "deps", "incremental"];
+
+/// Space kept free on the tmpfs for the ramdisk to grow during a build, 512 MiB
+pub const RESERVED_SPACE: u64 = 512 * 1024 * 1024;
+
+/// The ramdisk of a project, found in [`RAMDISK_ROOT`]
+#[derive(Debug, Clone)]
+pub struct Ramdisk {
+    pub path: PathBuf,
+    /// The project building on the ramdisk, unknown for ramdisks created by older versions
+    pub project: Option<PathBuf>,
+    pub last_used: SystemTime,
+    /// Size of the artifacts, in bytes
+    pub size: u64,
+    /// Whether the ramdisk belongs to the current user, the ramdisks of others are never evicted
+    pub owned: bool,
+}
+
+impl Ramdisk {
+    /// Checks whether the project of the ramdisk was removed
+    #[must_use]
+    pub fn is_orphaned(&self) -> bool {
+        self.project
+            .as_ref()
+            .is_some_and(|project| !project.join("Cargo.toml").exists())
+    }
+}
+
+/// Returns the ramdisk directory of the project with `fleet_id`
+#[must_use]
+pub fn ramdisk_dir(fleet_id: &str) -> PathBuf {
+    Path::new(RAMDISK_ROOT).join(fleet_id)
+}
+
+/// Returns the on-disk directory holding the snapshot of the ramdisk of the project with `fleet_id`
+#[must_use]
+pub fn snapshot_dir(fleet_id: &str) -> Option<PathBuf> {
+    Some(
+        dirs::cache_dir()?
+            .join("fleet")
+            .join("snapshots")
+            .join(fleet_id),
+    )
+}
+
+/// Returns the profile directories of a target directory, eg. `debug` or `x86_64-unknown-linux-gnu/release`
+fn profile_dirs(target_dir: &Path) -> Vec<PathBuf> {
+    let children = |dir: &Path| -> Vec<PathBuf> {
+        std::fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_dir())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    children(target_dir)
+        .into_iter()
+        .flat_map(|dir| std::iter::once(dir.clone()).chain(children(&dir)))
+        .filter(|dir| dir.join(".fingerprint").is_dir())
+        .collect()
+}
+
+/// Copies the intermediate artifacts of `ramdisk` to the snapshot of the project with `fleet_id`
+///
+/// Only [`SNAPSHOT_DIRS`] of each profile are kept, the snapshot is updated in place so that
+/// unchanged files are not copied again. Returns the size of the snapshot.
+pub fn snapshot(ramdisk: &Path, fleet_id: &str) -> Result<u64> {
+    let snapshot = snapshot_dir(fleet_id).context("failed to find the cache directory")?;
+    let temporary = snapshot.with_extension("partial");
+
+    if temporary.exists() {
+        std::fs::remove_dir_all(&temporary)
+            .with_context(|| format!("failed to remove {}", temporary.display()))?;
+    }
+
+    // Updated in place, then renamed so that an interrupted sync never leaves a broken snapshot
+    if snapshot.exists() {
+        std::fs::rename(&snapshot, &temporary)
+            .with_context(|| format!("failed to move {}", snapshot.display()))?;
+    }
+
+    std::fs::create_dir_all(&temporary)
+        .with_context(|| format!("failed to create {}", temporary.display()))?;
+
+    let profiles: Vec<PathBuf> = profile_dirs(ramdisk)
+        .iter()
+        .filter_map(|profile| profile.strip_prefix(ramdisk).ok().map(Path::to_path_buf))
+        .collect();
+
+    for profile in &profiles {
+        for name in SNAPSHOT_DIRS {
+            let from = ramdisk.join(profile).join(name);
+            let to = temporary.join(profile).join(name);
+
+            if from.is_dir() {
+                sync_dir(&from, &to)
+                    .with_context(|| format!("failed to copy {}", from.display()))?;
+            }
+        }
+    }
+
+    // Profiles removed from the ramdisk, eg. by `cargo clean`, are dropped from the snapshot
+    for profile in profile_dirs(&temporary) {
+        let kept = profile
+            .strip_prefix(&temporary)
+            .is_ok_and(|relative| profiles.iter().any(|kept| kept == relative));
+
+        if !kept {
+            std::fs::remove_dir_all(&profile)
+                .with_context(|| format!("failed to remove {}", profile.display()))?;
+        }
+    }
+
+    std::fs::rename(&temporary, &snapshot)
+        .with_context(|| format!("failed to move {}", temporary.display()))?;
+
+    Ok(dir_size(&snapshot))
+}
+
+/// Removes the snapshot of the project with `fleet_id`, returns `true` if there was one
+pub fn remove_snapshot(fleet_id: &str) -> Result<bool> {
+    let Some(snapshot) = snapshot_dir(fleet_id).filter(|snapshot| snapshot.exists()) else {
+        return Ok(false);
+    };
+
+    std::fs::remove_dir_all(&snapshot)
+        .with_context(|| format!("failed to remove {}", snapshot.display()))?;
+
+    Ok(true)
+}
+
+/// Formats a size in bytes for humans
+#[must_use]
+pub fn format_size(bytes: u64) -> String {
+    Byte::from_bytes(u128::from(bytes))
+        .get_appropriate_unit(true)
+        .to_string()
+}
+
+/// Returns the ramdisk of every project, the least recently used first
+///
+/// Only directories named after a `fleet_id` are considered, other files of the tmpfs are left alone.
+pub fn list() -> Result<Vec<Ramdisk>> {
+    let root = Path::new(RAMDISK_ROOT);
+
+    if !root.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let entries =
+        std::fs::read_dir(root).with_context(|| format!("failed to read {}", root.display()))?;
+
+    let mut ramdisks: Vec<Ramdisk> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| uuid::Uuid::parse_str(name).is_ok())
+        })
+        .map(|entry| {
+            let path = entry.path();
+            let project_file = path.join(PROJECT_FILE);
+
+            let last_used = std::fs::metadata(&project_file)
+                .or_else(|_| entry.metadata())
+                .and_then(|meta| meta.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+
+            Ramdisk {
+                project: std::fs::read_to_string(&project_file)
+                    .ok()
+                    .map(|project| PathBuf::from(project.trim())),
+                size: dir_size(&path),
+                owned: is_owned(&path),
+                last_used,
+                path,
+            }
+        })
+        .collect();
+
+    ramdisks.sort_by_key(|ramdisk| ramdisk.last_used);
+
+    Ok(ramdisks)
+}
+
+/// Removes ramdisks until `needed` bytes are free on the tmpfs, `keep` is never removed
+///
+/// Only the ramdisks of the current user are considered. Ramdisks whose project was removed go
+/// first, then the least recently used ones. Ramdisks that cannot be removed are skipped.
+/// Returns the removed ramdisks.
+pub fn evict(needed: u64, keep: &Path) -> Result<Vec<Ramdisk>> {
+    let (orphaned, used): (Vec<_>, Vec<_>) = list()?
+        .into_iter()
+        .filter(|ramdisk| ramdisk.owned && ramdisk.path != keep)
+        .partition(Ramdisk::is_orphaned);
+
+    let mut evicted = Vec::new();
+
+    for ramdisk in orphaned {
+        if std::fs::remove_dir_all(&ramdisk.path).is_ok() {
+            evicted.push(ramdisk);
+        }
+    }
+
+    for ramdisk in used {
+        if available_space(Path::new(RAMDISK_ROOT)).unwrap_or(0) >= needed {
+            break;
+        }
+
+        if std::fs::remove_dir_all(&ramdisk.path).is_ok() {
+            evicted.push(ramdisk);
+        }
+    }
+
+    Ok(evicted)
+}
+
+/// Asks a yes/no question, `false` when there is no terminal to answer it
+fn confirm(message: String) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+
+    let confirm = Confirm {
+        message: Cow::Owned(message),
+        default: false,
+    };
+
+    Ok(confirm.run()?)
+}
+
+/// Prepares the ramdisk of the project at `root_dir` and returns it
+///
+/// The ramdisk is used through `build.target-dir`, the `target` directory of the project is never
+/// deleted. When `first_use` is set and `target` holds artifacts, they are moved to the ramdisk
+/// once the user confirms it. Otherwise an empty ramdisk, eg. after a reboot, is restored from its
+/// snapshot when `rd_snapshot` is set.
+///
+/// Room for the ramdisk to grow by [`RESERVED_SPACE`], without going over `rd_size_limit`, is made
+/// by evicting the ramdisks of other projects. Fails when the ramdisk is over `rd_size_limit` or the tmpfs is still too full, in
+/// which case the project builds on disk.
+pub fn prepare(root_dir: &Path, config: &FleetConfig, first_use: bool) -> Result<PathBuf> {
+    let fleet_id = config.fleet_id.as_str();
+    let size_limit = config.rd_size_limit();
+
+    if !cfg!(target_os = "linux") || !Path::new(RAMDISK_ROOT).is_dir() {
+        bail!("ramdisks are only supported on Linux, with {RAMDISK_ROOT} mounted");
+    }
+
+    if fleet_id.is_empty() {
+        bail!("`fleet_id` is not set in fleet.toml");
+    }
+
+    let ramdisk = ramdisk_dir(fleet_id);
+    let target_dir = root_dir.join("target");
+
+    // Older versions of fleet replaced `target` with a symlink to the ramdisk
+    if target_dir.is_symlink() {
+        std::fs::remove_file(&target_dir)
+            .with_context(|| format!("failed to remove {}", target_dir.display()))?;
+    }
+
+    let size = dir_size(&ramdisk);
+
+    if let Some(limit) = size_limit {
+        if size > limit {
+            bail!(
+                "the ramdisk uses {}, over the `rd_size_limit` of {}, run `cargo clean` or raise the limit",
+                format_size(size),
+                format_size(limit)
+            );
+        }
+    }
+
+    let mut needed = size_limit.map_or(RESERVED_SPACE, |limit| RESERVED_SPACE.min(limit - size));
+
+    let target_size = if first_use && !is_non_empty_dir(&ramdisk) {
+        dir_size(&target_dir)
+    } else {
+        0
+    };
+
+    // Artifacts over the limit are left on disk
+    let move_target = target_size > 0 && size_limit.is_none_or(|limit| target_size <= limit);
+
+    if move_target {
+        needed += target_size;
+    }
+
+    let snapshot = snapshot_dir(fleet_id)
+        .filter(|snapshot| config.rd_snapshot && !move_target && snapshot.is_dir())
+        .filter(|_| !is_non_empty_dir(&ramdisk));
+
+    let snapshot_size = snapshot.as_deref().map_or(0, dir_size);
+
+    // Snapshots over the limit are not restored
+    let snapshot = snapshot.filter(|_| size_limit.is_none_or(|limit| snapshot_size <= limit));
+
+    if snapshot.is_some() {
+        needed += snapshot_size;
+    }
+
+    for evicted in evict(needed, &ramdisk)? {
+        println!(
+            "💽 Removed the ramdisk of {} ({})",
+            evicted
+                .project
+                .as_deref()
+                .map_or_else(|| evicted.path.display(), Path::display),
+            format_size(evicted.size)
+        );
+    }
+
+    let available = available_space(Path::new(RAMDISK_ROOT)).unwrap_or(0);
+
+    if available < needed {
+        bail!(
+            "only {} is free on {RAMDISK_ROOT}, {} is needed",
+            format_size(available),
+            format_size(needed)
+        );
+    }
+
+    if move_target
+        && confirm(format!(
+            "Move the existing target directory to the ramdisk at {}?",
+            ramdisk.display()
+        ))?
+    {
+        move_dir(&target_dir, &ramdisk).with_context(|| {
+            format!(
+                "failed to move {} to {}",
+                target_dir.display(),
+                ramdisk.display()
+            )
+        })?;
+
+        println!("💽 Moved target to the ramdisk");
+    }
+
+    if !ramdisk.exists() {
+        std::fs::create_dir_all(&ramdisk)
+            .with_context(|| format!("failed to create {}", ramdisk.display()))?;
+
+        println!("💽 Creating Ramdisk");
+    }
+
+    if let Some(snapshot) = snapshot {
+        sync_dir(&snapshot, &ramdisk).with_context(|| {
+            format!(
+                "failed to restore the snapshot {} to {}",
+                snapshot.display(),
+                ramdisk.display()
+            )
+        })?;
+
+        println!("💾 Restored the ramdisk from its snapshot");
+    }
+
+    let project_file = ramdisk.join(PROJECT_FILE);
+
+    std::fs::write(&project_file, root_dir.to_string_lossy().as_bytes())
+        .with_context(|| format!("failed to write {}", project_file.display()))?;
+
+    Ok(ramdisk)
+}
+
+/// Returns the ramdisk the `target` symlink of the project points to, if any
+///
+/// Older versions of fleet linked `target` to the ramdisk without recording it in
+/// `fleet-managed.toml`.
+#[must_use]
+pub fn linked_ramdisk(root_dir: &Path) -> Option<PathBuf> {
+    let ramdisk = std::fs::read_link(root_dir.join("target")).ok()?;
+
+    ramdisk.starts_with(RAMDISK_ROOT).then_some(ramdisk)
+}
+
+/// Moves the artifacts of the ramdisk back into the `target` directory of the project
+///
+/// The ramdisk is kept when `target` already holds artifacts, so that neither is lost. A `target`
+/// symlink to a ramdisk that no longer exists is removed.
+/// Returns `true` if the ramdisk was moved.
+pub fn restore_target(root_dir: &Path, ramdisk: &Path) -> Result<bool> {
+    let target_dir = root_dir.join("target");
+
+    if !ramdisk.exists() {
+        if std::fs::read_link(&target_dir).is_ok_and(|link| link == ramdisk) {
+            std::fs::remove_file(&target_dir)
+                .with_context(|| format!("failed to remove {}", target_dir.display()))?;
+        }
+
+        return Ok(false);
+    }
+
+    if target_dir.is_symlink() {
+        std::fs::remove_file(&target_dir)
+            .with_context(|| format!("failed to remove {}", target_dir.display()))?;
+    }
+
+    if is_non_empty_dir(&target_dir) {
+        return Ok(false);
+    }
+
+    let project_file = ramdisk.join(PROJECT_FILE);
+
+    if project_file.exists() {
+        std::fs::remove_file(&project_file)
+            .with_context(|| format!("failed to remove {}", project_file.display()))?;
+    }
+
+    if target_dir.exists() {
+        std::fs::remove_dir(&target_dir)
+            .with_context(|| format!("failed to remove {}", target_dir.display()))?;
+    }
+
+    move_dir(ramdisk, &target_dir).with_context(|| {
+        format!(
+            "failed to move {} to {}",
+            ramdisk.display(),
+            target_dir.display()
+        )
+    })?;
+
+    Ok(true)
+}
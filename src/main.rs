@@ -20,7 +20,7 @@
 //!
 //! Note: Since fleet is in the beta phase, it might not be completely stable yet. Feel free to open any issues or bug reports at issues.
 //!
-//! Note: As of now fleet only supports rustc nightly
+//! Note: Some optimizations (eg. `-Zshare-generics`) are only applied when using a nightly toolchain
 //!
 #![warn(clippy::all)]
 #![warn(clippy::pedantic)]
@@ -46,5 +46,5 @@ fn main() -> Result<()> {
     #[cfg(windows)]
     let _ = ansi_term::enable_ansi_support();
 
-    App::new().run()
+    App::new()?.run()
 }
@@ -15,92 +15,115 @@
  *    limitations under the License.
  */
 
-use std::env::consts::OS;
+use std::{borrow::Cow, env::consts::OS, path::PathBuf, process::Command};
 
+use anyhow::{bail, Context, Result};
 use colored::Colorize;
 
+use crate::cli::prompt::prompts::Confirm;
+use crate::core::config::{find, Linker};
+use crate::utils::package_manager::PackageManager;
+
+/// Paths of the `binaries`, keyed by their name in the `build` table, `None` if one is missing
+fn find_all(binaries: &[(&'static str, &str)]) -> Option<Vec<(&'static str, PathBuf)>> {
+    binaries
+        .iter()
+        .map(|(key, binary)| Some((*key, find(binary)?)))
+        .collect()
+}
+
 /// Installs a linker
 ///
 /// Installs the specified linker using the appropriate
 /// package manager for the user operating system
 ///
-/// # Panics
-/// Can panic if unable to install clang and lld
-pub fn install_linker(linker: &str) {
-    match OS {
-        "windows" => {
-            if linker.contains("lld") {
-                // LLD ships with Rust:
-                println!("🚄 lld {}", "enabled".bright_green());
-            }
-        }
-        "linux" => {
-            if linker.contains("mold") {
-                println!("🚀 mold {}", "enabled".bright_green());
-            }
-
-            if linker.contains("lld") {
-                println!(
-                    "{} {}",
-                    "$".bright_black(),
-                    "sudo apt install clang lld".bright_yellow()
-                );
-
-                // TODO: allow for user confirmation on whether or not they want to run the command
-                // TODO: add distro support
-                println!("{:?}", sys_info::linux_os_release());
-
-                let result = std::process::Command::new("bash")
-                    .arg("-c")
-                    .arg("sudo apt install clang lld")
-                    .status()
-                    .expect("Failed to install clang + lld");
-
-                if result.success() {
-                    println!("🚄 lld {}", "enabled".bright_green());
-                }
-            }
-        }
-        "macos" => {
-            if linker.contains("zld") {
-                println!(
-                    "{} {}",
-                    "$".bright_black(),
-                    "brew install michaeleisel/zld/zld".bright_yellow()
-                );
-
-                // TODO: allow for user confirmation on whether or not they want to run the command
-                // Spawn the command
-                let result = std::process::Command::new("bash")
-                    .arg("-c")
-                    .arg("brew install michaeleisel/zld/zld")
-                    .status()
-                    .unwrap();
-
-                if result.success() {
-                    println!("🚀 zld {}", "enabled".bright_green());
-                }
-            }
-
-            if linker.contains("lld") {
-                println!(
-                    "{} {}",
-                    "$".bright_black(),
-                    "sudo apt install clang lld".bright_yellow()
-                );
-
-                // TODO: allow for user confirmation on whether or not they want to run the command
-                let result = std::process::Command::new("bash")
-                    .arg("-c")
-                    .arg("sudo apt install clang lld")
-                    .status()
-                    .unwrap();
-
-                if result.success() {
-                    println!("🚄 lld {}", "enabled".bright_green());
-                }
-            }
+/// The install command is shown and only run once the user confirms it,
+/// with `dry_run` the command is only printed.
+/// Returns the paths of the binaries of the linker, keyed by their name in the `build` table,
+/// empty when the linker is not installed.
+pub fn install_linker(linker: Linker, dry_run: bool) -> Result<Vec<(&'static str, PathBuf)>> {
+    let (emoji, binaries): (&str, &[(&str, &str)]) = match (OS, linker) {
+        (_, Linker::System) => return Ok(Vec::new()),
+        // LLD ships with Rust:
+        ("windows", Linker::Lld) => ("🚄", &[]),
+        ("linux", Linker::Lld) => ("🚄", &[("clang", "clang"), ("lld", "ld.lld")]),
+        (_, Linker::Lld) => ("🚄", &[("lld", "ld64.lld")]),
+        (_, Linker::Mold) => ("🚀", &[("mold", "mold")]),
+        (_, Linker::Zld) => ("🚀", &[("zld", "zld")]),
+    };
+
+    if let Some(paths) = find_all(binaries) {
+        println!("{} {} {}", emoji, linker.as_str(), "enabled".bright_green());
+        return Ok(paths);
+    }
+
+    let package_manager = PackageManager::detect();
+
+    let packages = match (package_manager, linker) {
+        (Some(package_manager), Linker::Lld) => package_manager.lld_packages(),
+        (Some(PackageManager::Brew), Linker::Zld) => &["michaeleisel/zld/zld"],
+        (Some(PackageManager::Brew), Linker::Mold) | (Some(_), Linker::Zld) | (None, _) => &[],
+        (Some(_), _) => &["mold"],
+    };
+
+    let package_manager = match package_manager {
+        Some(package_manager) if !packages.is_empty() => package_manager,
+        _ => {
+            println!(
+                "{}: {} could not be installed automatically, please install it manually and run `fleet configure` again",
+                "warning".bright_yellow(),
+                linker.as_str().bright_cyan()
+            );
+
+            return Ok(Vec::new());
         }
-        &_ => {}
+    };
+
+    let command = package_manager.install_command(packages);
+
+    println!(
+        "{} {}",
+        "$".bright_black(),
+        command.join(" ").bright_yellow()
+    );
+
+    if dry_run {
+        return Ok(Vec::new());
+    }
+
+    let confirm = Confirm {
+        message: Cow::Borrowed("Run this command?"),
+        default: true,
+    };
+
+    if !confirm.run()? {
+        return Ok(Vec::new());
+    }
+
+    let status = Command::new(&command[0])
+        .args(&command[1..])
+        .status()
+        .with_context(|| format!("failed to run `{}`", command.join(" ")))?;
+
+    if !status.success() {
+        bail!(
+            "failed to install {}, `{}` exited with {status}",
+            linker.as_str(),
+            command.join(" ")
+        );
     }
+
+    let Some(paths) = find_all(binaries) else {
+        println!(
+            "{}: {} was installed but could not be found in PATH",
+            "warning".bright_yellow(),
+            linker.as_str().bright_cyan()
+        );
+
+        return Ok(Vec::new());
+    };
+
+    println!("{} {} {}", emoji, linker.as_str(), "enabled".bright_green());
+
+    Ok(paths)
 }
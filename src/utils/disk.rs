@@ -0,0 +1,508 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::path::Path;
+
+/// Returns the space available to the current user on the filesystem holding `path`, in bytes
+#[cfg(unix)]
+#[must_use]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: `path` is a valid C string and `stat` is only read once `statvfs` succeeded
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+
+    let stat = unsafe { stat.assume_init() };
+
+    #[allow(clippy::unnecessary_cast)]
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Returns the space available to the current user on the filesystem holding `path`, in bytes
+#[cfg(not(unix))]
+#[must_use]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Checks whether `path` belongs to the current user
+#[cfg(unix)]
+#[must_use]
+pub fn is_owned(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    // SAFETY: `geteuid` has no preconditions and always succeeds
+    let uid = unsafe { libc::geteuid() };
+
+    std::fs::symlink_metadata(path).is_ok_and(|meta| meta.uid() == uid)
+}
+
+/// Checks whether `path` belongs to the current user
+#[cfg(not(unix))]
+#[must_use]
+pub fn is_owned(_path: &Path) -> bool {
+    true
+}
+
+/// Copies the directory `from` into `to` recursively, `to` is created if needed
+///
+/// Symlinks are copied as symlinks on unix and skipped elsewhere.
+pub fn copy_dir_all(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let destination = to.join(entry.file_name());
+
+        if file_type.is_dir() {
+            copy_dir_all(&entry.path(), &destination)?;
+        } else if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(std::fs::read_link(entry.path())?, &destination)?;
+        } else {
+            std::fs::copy(entry.path(), &destination)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves the directory `from` to `to`, copying it when they are on different filesystems
+///
+/// `from` is only removed once every file has been copied.
+pub fn move_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    copy_dir_all(from, to)?;
+    std::fs::remove_dir_all(from)
+}
+
+/// Checks whether `path` is a directory with at least one entry
+#[must_use]
+pub fn is_non_empty_dir(path: &Path) -> bool {
+    std::fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_some())
+}
+
+/// Returns the size of the files in the directory `path` recursively, in bytes
+///
+/// Symlinks are not followed, entries that cannot be read are skipped.
+#[must_use]
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            Ok(file_type) if file_type.is_file() => entry.metadata().map_or(0, |meta| meta.len()),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Mirrors the directory `from` into `to`, `to` is created if needed
+///
+/// Only files whose size or modification time differ are copied, and their modification time is
+/// kept so that cargo still considers them fresh. Entries of `to` missing from `from` are removed.
+pub fn sync_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+
+    let mut names = std::collections::HashSet::new();
+
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let destination = to.join(entry.file_name());
+
+        names.insert(entry.file_name());
+
+        if file_type.is_dir() {
+            if destination.is_file() || destination.is_symlink() {
+                std::fs::remove_file(&destination)?;
+            }
+
+            sync_dir(&entry.path(), &destination)?;
+        } else if file_type.is_file() {
+            let meta = entry.metadata()?;
+            let modified = meta.modified()?;
+
+            let fresh = std::fs::symlink_metadata(&destination).is_ok_and(|existing| {
+                existing.is_file()
+                    && existing.len() == meta.len()
+                    && existing.modified().is_ok_and(|time| time == modified)
+            });
+
+            if !fresh {
+                if destination.is_dir() {
+                    std::fs::remove_dir_all(&destination)?;
+                }
+
+                std::fs::copy(entry.path(), &destination)?;
+                std::fs::File::options()
+                    .write(true)
+                    .open(&destination)?
+                    .set_modified(modified)?;
+            }
+        }
+    }
+
+    for entry in std::fs::read_dir(to)? {
+        let entry = entry?;
+
+        if names.contains(&entry.file_name()) {
+            continue;
+        }
+
+        if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Kind of storage holding a directory, see [`disk_kind`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiskKind {
+    /// A spinning hard drive
+    Rotational,
+    SolidState,
+    /// A network or host filesystem, eg. NFS or the Windows drives of WSL
+    Network,
+    /// A container filesystem layered over another one, eg. overlayfs
+    Overlay,
+    /// A filesystem held in memory, eg. tmpfs
+    Memory,
+    /// The storage could not be determined
+    Unknown,
+}
+
+impl std::fmt::Display for DiskKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DiskKind::Rotational => "hard drive",
+            DiskKind::SolidState => "solid state drive",
+            DiskKind::Network => "network filesystem",
+            DiskKind::Overlay => "overlay filesystem",
+            DiskKind::Memory => "memory",
+            DiskKind::Unknown => "unknown",
+        })
+    }
+}
+
+impl DiskKind {
+    /// Checks whether building on a ramdisk is noticeably faster than building on this storage
+    #[must_use]
+    pub fn is_slow(self) -> bool {
+        matches!(self, DiskKind::Rotational | DiskKind::Network)
+    }
+}
+
+/// A line of `/proc/mounts`
+#[cfg(target_os = "linux")]
+struct Mount {
+    device: String,
+    /// Where the filesystem is mounted
+    path: std::path::PathBuf,
+    fs_type: String,
+}
+
+/// Unescapes a field of `/proc/mounts`, spaces and tabs are escaped as octal, eg. `\040`
+#[cfg(target_os = "linux")]
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = field;
+
+    while let Some(index) = rest.find('\\') {
+        unescaped.push_str(&rest[..index]);
+
+        let code = rest.get(index + 1..index + 4);
+
+        if let Some(byte) = code.and_then(|code| u8::from_str_radix(code, 8).ok()) {
+            unescaped.push(char::from(byte));
+            rest = &rest[index + 4..];
+        } else {
+            unescaped.push('\\');
+            rest = &rest[index + 1..];
+        }
+    }
+
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Parses the mounted filesystems from the contents of `/proc/mounts`
+#[cfg(target_os = "linux")]
+fn parse_mounts(contents: &str) -> Vec<Mount> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+
+            Some(Mount {
+                device: unescape(fields.next()?),
+                path: unescape(fields.next()?).into(),
+                fs_type: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the mount holding `path`, the one with the longest mount point
+#[cfg(target_os = "linux")]
+fn find_mount<'a>(mounts: &'a [Mount], path: &Path) -> Option<&'a Mount> {
+    mounts
+        .iter()
+        .filter(|mount| path.starts_with(&mount.path))
+        .max_by_key(|mount| mount.path.components().count())
+}
+
+/// Returns the kind of storage of a filesystem type, `None` for filesystems on a block device
+#[cfg(target_os = "linux")]
+fn fs_kind(fs_type: &str) -> Option<DiskKind> {
+    const NETWORK: &[&str] = &[
+        "nfs",
+        "nfs4",
+        "cifs",
+        "smb3",
+        "smbfs",
+        "9p",
+        "drvfs",
+        "ceph",
+        "glusterfs",
+        "afs",
+        "fuse.sshfs",
+        "virtiofs",
+    ];
+    const OVERLAY: &[&str] = &["overlay", "aufs", "fuse-overlayfs"];
+    const MEMORY: &[&str] = &["tmpfs", "ramfs"];
+
+    if NETWORK.contains(&fs_type) {
+        Some(DiskKind::Network)
+    } else if OVERLAY.contains(&fs_type) {
+        Some(DiskKind::Overlay)
+    } else if MEMORY.contains(&fs_type) {
+        Some(DiskKind::Memory)
+    } else {
+        None
+    }
+}
+
+/// Parses the contents of `queue/rotational` in sysfs
+#[cfg(target_os = "linux")]
+fn parse_rotational(contents: &str) -> Option<bool> {
+    match contents.trim() {
+        "1" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Reads whether the block device at `sys_dir` in sysfs is rotational
+///
+/// Partitions are resolved to their disk, and device mapper or RAID devices are rotational as
+/// soon as one of their underlying devices is.
+#[cfg(target_os = "linux")]
+fn is_rotational(sys_dir: &Path) -> Option<bool> {
+    let sys_dir = sys_dir.canonicalize().ok()?;
+
+    if sys_dir.join("partition").exists() {
+        return is_rotational(sys_dir.parent()?);
+    }
+
+    let slaves: Vec<_> = std::fs::read_dir(sys_dir.join("slaves"))
+        .map(|entries| entries.filter_map(Result::ok).collect())
+        .unwrap_or_default();
+
+    if !slaves.is_empty() {
+        let rotational: Vec<bool> = slaves
+            .iter()
+            .filter_map(|slave| is_rotational(&slave.path()))
+            .collect();
+
+        return (!rotational.is_empty()).then(|| rotational.contains(&true));
+    }
+
+    parse_rotational(&std::fs::read_to_string(sys_dir.join("queue").join("rotational")).ok()?)
+}
+
+/// Finds the kind of storage holding `path`
+///
+/// The mount point containing `path` is looked up in `/proc/mounts`, network and in-memory
+/// filesystems are recognized by their type. For block devices, `/sys/dev/block` tells whether
+/// the disk is rotational. [`DiskKind::Unknown`] is returned when any of these cannot be read,
+/// eg. in containers or outside of Linux.
+#[must_use]
+pub fn disk_kind(path: &Path) -> DiskKind {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let Ok(path) = path.canonicalize() else {
+            return DiskKind::Unknown;
+        };
+
+        let mounts = parse_mounts(&std::fs::read_to_string("/proc/mounts").unwrap_or_default());
+
+        let Some(mount) = find_mount(&mounts, &path) else {
+            return DiskKind::Unknown;
+        };
+
+        if let Some(kind) = fs_kind(&mount.fs_type) {
+            return kind;
+        }
+
+        // Filesystems such as btrfs report an anonymous device, the mounted device is used instead
+        let by_number = std::fs::metadata(&path).ok().and_then(|meta| {
+            let dev = meta.dev();
+            let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+            let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+
+            is_rotational(&Path::new("/sys/dev/block").join(format!("{major}:{minor}")))
+        });
+
+        let by_name = || {
+            let device = Path::new(&mount.device).canonicalize().ok()?;
+            let name = device.file_name()?;
+
+            is_rotational(&Path::new("/sys/class/block").join(name))
+        };
+
+        match by_number.or_else(by_name) {
+            Some(true) => DiskKind::Rotational,
+            Some(false) => DiskKind::SolidState,
+            None => DiskKind::Unknown,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = path;
+
+        DiskKind::Unknown
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::{find_mount, fs_kind, is_rotational, parse_mounts, parse_rotational, DiskKind};
+    use std::path::Path;
+
+    const MOUNTS: &str = "\
+/dev/nvme0n1p2 / ext4 rw,relatime 0 0
+tmpfs /dev/shm tmpfs rw,nosuid,nodev 0 0
+/dev/sda1 /home/user/My\\040Projects ext4 rw,relatime 0 0
+C:\\134 /mnt/c 9p rw,noatime 0 0
+overlay /var/lib/docker/overlay2/abc/merged overlay rw 0 0
+";
+
+    #[test]
+    fn mount_points_are_unescaped() {
+        let mounts = parse_mounts(MOUNTS);
+
+        assert_eq!(mounts.len(), 5);
+        assert_eq!(mounts[2].path, Path::new("/home/user/My Projects"));
+        assert_eq!(mounts[3].device, "C:\\");
+        assert_eq!(mounts[3].fs_type, "9p");
+    }
+
+    #[test]
+    fn longest_mount_point_wins() {
+        let mounts = parse_mounts(MOUNTS);
+        let device =
+            |path: &str| find_mount(&mounts, Path::new(path)).map(|mount| mount.device.as_str());
+
+        assert_eq!(
+            device("/home/user/My Projects/fleet/target"),
+            Some("/dev/sda1")
+        );
+        assert_eq!(device("/home/user/My"), Some("/dev/nvme0n1p2"));
+        assert_eq!(device("/dev/shm/fleet"), Some("tmpfs"));
+        assert_eq!(device("/dev/shmem"), Some("/dev/nvme0n1p2"));
+        assert_eq!(
+            find_mount(&[], Path::new("/")).map(|mount| &mount.device),
+            None
+        );
+    }
+
+    #[test]
+    fn filesystem_types() {
+        assert_eq!(fs_kind("tmpfs"), Some(DiskKind::Memory));
+        assert_eq!(fs_kind("9p"), Some(DiskKind::Network));
+        assert_eq!(fs_kind("overlay"), Some(DiskKind::Overlay));
+        assert_eq!(fs_kind("ext4"), None);
+    }
+
+    #[test]
+    fn rotational_values() {
+        assert_eq!(parse_rotational("1\n"), Some(true));
+        assert_eq!(parse_rotational("0\n"), Some(false));
+        assert_eq!(parse_rotational(""), None);
+    }
+
+    #[test]
+    fn rotational_devices_in_sysfs() {
+        let sys = std::env::temp_dir().join(format!("fleet-sysfs-{}", std::process::id()));
+
+        let device = |name: &str, rotational: &str| {
+            let queue = sys.join(name).join("queue");
+            std::fs::create_dir_all(&queue).unwrap();
+            std::fs::write(queue.join("rotational"), rotational).unwrap();
+        };
+
+        device("sda", "1\n");
+        device("nvme0n1", "0\n");
+
+        // A partition is resolved to its disk
+        std::fs::create_dir_all(sys.join("sda").join("sda1")).unwrap();
+        std::fs::write(sys.join("sda").join("sda1").join("partition"), "1\n").unwrap();
+
+        // A device mapper is rotational as soon as one of its devices is
+        let slaves = sys.join("dm-0").join("slaves");
+        std::fs::create_dir_all(&slaves).unwrap();
+        std::os::unix::fs::symlink(sys.join("sda"), slaves.join("sda")).unwrap();
+        std::os::unix::fs::symlink(sys.join("nvme0n1"), slaves.join("nvme0n1")).unwrap();
+
+        let results = [
+            is_rotational(&sys.join("sda")),
+            is_rotational(&sys.join("nvme0n1")),
+            is_rotational(&sys.join("sda").join("sda1")),
+            is_rotational(&sys.join("dm-0")),
+            is_rotational(&sys.join("missing")),
+        ];
+
+        std::fs::remove_dir_all(&sys).unwrap();
+
+        assert_eq!(
+            results,
+            [Some(true), Some(false), Some(true), Some(true), None]
+        );
+    }
+}
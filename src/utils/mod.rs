@@ -17,3 +17,6 @@
 
 /// Utility features used within the fleet application
 pub mod configure;
+pub mod disk;
+pub mod package_manager;
+pub mod process;
@@ -0,0 +1,216 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::{env::consts::OS, path::Path};
+
+/// Package managers fleet knows how to install linkers with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageManager {
+    Apt,
+    Dnf,
+    Pacman,
+    Zypper,
+    Apk,
+    Brew,
+}
+
+impl PackageManager {
+    /// Detects the package manager of the current system
+    ///
+    /// On Linux the distribution is read from `/etc/os-release`, or `/usr/lib/os-release`.
+    #[must_use]
+    pub fn detect() -> Option<Self> {
+        match OS {
+            "macos" => Some(PackageManager::Brew),
+            "linux" => Self::from_os_release(Path::new("/etc/os-release"))
+                .or_else(|| Self::from_os_release(Path::new("/usr/lib/os-release"))),
+            _ => None,
+        }
+    }
+
+    /// Detects the package manager from an os-release file, eg. `/etc/os-release`
+    #[must_use]
+    pub fn from_os_release(path: &Path) -> Option<Self> {
+        let contents = std::fs::read_to_string(path).ok()?;
+
+        Self::from_os_release_contents(&contents)
+    }
+
+    /// Detects the package manager from the contents of an os-release file
+    #[must_use]
+    pub fn from_os_release_contents(contents: &str) -> Option<Self> {
+        let mut id = None;
+        let mut id_like = None;
+
+        for line in contents.lines() {
+            if let Some((key, value)) = line.trim().split_once('=') {
+                let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+
+                match key {
+                    "ID" => id = Some(value),
+                    "ID_LIKE" => id_like = Some(value),
+                    _ => {}
+                }
+            }
+        }
+
+        Self::from_ids(id, id_like)
+    }
+
+    /// Detects the package manager from the `ID` and `ID_LIKE` fields of os-release
+    ///
+    /// The `ID` of the distribution is checked first, then every distribution in `ID_LIKE`.
+    fn from_ids(id: Option<&str>, id_like: Option<&str>) -> Option<Self> {
+        id.into_iter()
+            .chain(id_like)
+            .flat_map(str::split_whitespace)
+            .find_map(|id| Self::from_distro(&id.to_lowercase()))
+    }
+
+    /// Maps a distribution id to its package manager
+    fn from_distro(id: &str) -> Option<Self> {
+        match id {
+            "debian" | "ubuntu" | "linuxmint" | "pop" | "elementary" | "raspbian" => {
+                Some(PackageManager::Apt)
+            }
+            "fedora" | "rhel" | "centos" | "rocky" | "almalinux" | "amzn" => {
+                Some(PackageManager::Dnf)
+            }
+            "arch" | "manjaro" | "endeavouros" | "garuda" => Some(PackageManager::Pacman),
+            "alpine" => Some(PackageManager::Apk),
+            id if id == "suse" || id == "sles" || id.starts_with("opensuse") => {
+                Some(PackageManager::Zypper)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the command used to install `packages`
+    #[must_use]
+    pub fn install_command(self, packages: &[&str]) -> Vec<String> {
+        let base: &[&str] = match self {
+            PackageManager::Apt => &["sudo", "apt", "install"],
+            PackageManager::Dnf => &["sudo", "dnf", "install"],
+            PackageManager::Pacman => &["sudo", "pacman", "-S"],
+            PackageManager::Zypper => &["sudo", "zypper", "install"],
+            PackageManager::Apk => &["sudo", "apk", "add"],
+            PackageManager::Brew => &["brew", "install"],
+        };
+
+        base.iter()
+            .chain(packages.iter())
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// Returns the packages providing `clang` and `lld`
+    #[must_use]
+    pub fn lld_packages(self) -> &'static [&'static str] {
+        match self {
+            PackageManager::Brew => &["llvm"],
+            _ => &["clang", "lld"],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debian() {
+        let contents = r#"PRETTY_NAME="Debian GNU/Linux 12 (bookworm)"
+NAME="Debian GNU/Linux"
+VERSION_ID="12"
+ID=debian
+HOME_URL="https://www.debian.org/"
+"#;
+
+        assert_eq!(
+            PackageManager::from_os_release_contents(contents),
+            Some(PackageManager::Apt)
+        );
+    }
+
+    #[test]
+    fn fedora() {
+        let contents = r#"NAME="Fedora Linux"
+VERSION="39 (Workstation Edition)"
+ID=fedora
+VERSION_ID=39
+"#;
+
+        assert_eq!(
+            PackageManager::from_os_release_contents(contents),
+            Some(PackageManager::Dnf)
+        );
+    }
+
+    #[test]
+    fn arch() {
+        let contents = "NAME=\"Arch Linux\"\nID=arch\nBUILD_ID=rolling\n";
+
+        assert_eq!(
+            PackageManager::from_os_release_contents(contents),
+            Some(PackageManager::Pacman)
+        );
+    }
+
+    #[test]
+    fn id_like_fallback() {
+        let contents = r#"NAME="KDE neon"
+ID=neon
+ID_LIKE="ubuntu debian"
+"#;
+
+        assert_eq!(
+            PackageManager::from_os_release_contents(contents),
+            Some(PackageManager::Apt)
+        );
+
+        let contents = "ID='rocky'\nID_LIKE='rhel centos fedora'\n";
+
+        assert_eq!(
+            PackageManager::from_os_release_contents(contents),
+            Some(PackageManager::Dnf)
+        );
+    }
+
+    #[test]
+    fn unknown_distribution() {
+        assert_eq!(PackageManager::from_os_release_contents("ID=nixos\n"), None);
+        assert_eq!(PackageManager::from_os_release_contents(""), None);
+    }
+
+    #[test]
+    fn os_release_file() {
+        let path = std::env::temp_dir().join(format!("fleet-os-release-{}", std::process::id()));
+
+        std::fs::write(
+            &path,
+            "NAME=openSUSE Tumbleweed\nID=\"opensuse-tumbleweed\"\nID_LIKE=\"opensuse suse\"\n",
+        )
+        .unwrap();
+
+        let detected = PackageManager::from_os_release(&path);
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(detected, Some(PackageManager::Zypper));
+        assert_eq!(PackageManager::from_os_release(&path), None);
+    }
+}
@@ -0,0 +1,169 @@
+/*
+ *
+ *    Copyright 2021 Fleet Contributors
+ *
+ *    Licensed under the Apache License, Version 2.0 (the "License");
+ *    you may not use this file except in compliance with the License.
+ *    You may obtain a copy of the License at
+ *
+ *        http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *    Unless required by applicable law or agreed to in writing, software
+ *    distributed under the License is distributed on an "AS IS" BASIS,
+ *    WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *    See the License for the specific language governing permissions and
+ *    limitations under the License.
+ */
+
+use std::process::{Command, ExitStatus};
+
+#[cfg(unix)]
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// Pid of the child that `status_forwarding_signals` is waiting on, 0 when there is none
+#[cfg(unix)]
+static CHILD: AtomicI32 = AtomicI32::new(0);
+
+/// Signal received before the pid of the child was known, 0 when there is none
+#[cfg(unix)]
+static PENDING: AtomicI32 = AtomicI32::new(0);
+
+/// Exits fleet the way the child with `status` exited, with its code or by its signal
+pub fn exit_with(status: ExitStatus) -> ! {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            // SAFETY: `signal` and `raise` are called with a valid signal number and the default
+            // disposition, no handler of fleet runs afterwards
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+
+            // The signal did not terminate fleet, use the code a shell would report
+            std::process::exit(128 + signal);
+        }
+    }
+
+    std::process::exit(status.code().unwrap_or(1))
+}
+
+/// Runs `command` and waits for it, forwarding SIGINT and SIGTERM sent to fleet to the child
+///
+/// Signals sent by the terminal reach the child through its process group, those are not sent
+/// again. Fleet itself keeps running until the child has exited.
+///
+/// The handlers are installed before the child is spawned, a signal received before its pid is
+/// known is forwarded right after the spawn. The child does not inherit the handlers, exec resets
+/// them to their default.
+#[cfg(unix)]
+pub fn status_forwarding_signals(command: &mut Command) -> std::io::Result<ExitStatus> {
+    const SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+    PENDING.store(0, Ordering::SeqCst);
+
+    // SAFETY: `sigaction` is a plain C struct for which all zeroes is a valid value, it is only
+    // used as an output of `sigaction` below
+    let mut previous: [libc::sigaction; 2] = unsafe { std::mem::zeroed() };
+
+    // SAFETY: `action` is fully initialized before use, `forward_signal` has the signature
+    // required by `SA_SIGINFO` and only calls async-signal-safe functions, and every pointer
+    // passed to `sigemptyset` and `sigaction` is valid for the duration of the call
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+
+        let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+            forward_signal;
+
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+        libc::sigemptyset(std::ptr::addr_of_mut!(action.sa_mask));
+
+        for (signal, previous) in SIGNALS.iter().zip(&mut previous) {
+            libc::sigaction(*signal, std::ptr::addr_of!(action), previous);
+        }
+    }
+
+    let status = command.spawn().and_then(|mut child| {
+        let pid = libc::pid_t::try_from(child.id()).unwrap_or_default();
+
+        CHILD.store(pid, Ordering::SeqCst);
+
+        let pending = PENDING.swap(0, Ordering::SeqCst);
+
+        if pending != 0 {
+            // SAFETY: `pid` is the child that was just spawned and has not been waited on, so
+            // it cannot have been reused by another process
+            unsafe {
+                libc::kill(pid, pending);
+            }
+        }
+
+        child.wait()
+    });
+
+    CHILD.store(0, Ordering::SeqCst);
+
+    // SAFETY: `previous` holds the actions returned by `sigaction` above
+    unsafe {
+        for (signal, previous) in SIGNALS.iter().zip(&previous) {
+            libc::sigaction(*signal, previous, std::ptr::null_mut());
+        }
+    }
+
+    // The child could not be spawned, the signal is handled as if no handler had been installed
+    let pending = PENDING.swap(0, Ordering::SeqCst);
+
+    if status.is_err() && pending != 0 {
+        // SAFETY: `pending` is a valid signal number received by the handler
+        unsafe {
+            libc::raise(pending);
+        }
+    }
+
+    status
+}
+
+/// Runs `command` and waits for it
+#[cfg(not(unix))]
+pub fn status_forwarding_signals(command: &mut Command) -> std::io::Result<ExitStatus> {
+    command.status()
+}
+
+/// Handler of SIGINT and SIGTERM, only uses atomics and async-signal-safe functions
+///
+/// A signal received before the child is spawned is kept in [`PENDING`].
+#[cfg(unix)]
+extern "C" fn forward_signal(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    let child = CHILD.load(Ordering::SeqCst);
+
+    if child == 0 {
+        PENDING.store(signal, Ordering::SeqCst);
+    } else if child > 0 && !from_terminal(signal, info) {
+        // SAFETY: `kill` is async-signal-safe, `child` is only reset to 0 after it was waited on
+        unsafe {
+            libc::kill(child, signal);
+        }
+    }
+}
+
+/// Whether `signal` was generated by the terminal, which sends it to the whole foreground group
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn from_terminal(_signal: libc::c_int, info: *mut libc::siginfo_t) -> bool {
+    // SAFETY: the kernel passes a valid `siginfo_t` to handlers installed with `SA_SIGINFO`
+    !info.is_null() && unsafe { (*info).si_code } == libc::SI_KERNEL
+}
+
+/// Whether `signal` was generated by the terminal, which sends it to the whole foreground group
+///
+/// The sender cannot be told apart here, an interrupt is assumed to come from the terminal.
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn from_terminal(signal: libc::c_int, _info: *mut libc::siginfo_t) -> bool {
+    signal == libc::SIGINT
+}